@@ -10,12 +10,48 @@ constexpr static const uint64_t LOGGING_THREAD_TIMEOUT = 1;
 
 constexpr static const uintptr_t NUM_LOGGING_THREADS = 4;
 
+constexpr static const uintptr_t LOGGING_BATCH_SIZE = 256;
+
 constexpr static const uintptr_t NO_INIT_STATE = 0;
 
 constexpr static const uintptr_t DO_INIT_STATE = 1;
 
 constexpr static const uintptr_t INIT_STATE = 2;
 
+/// How records are laid out in log files.
+enum class LogFormat {
+  /// The padded, human-readable lines the logger always wrote.
+  Text,
+  /// Compact varint-framed records.
+  Binary,
+  /// One JSON object per line.
+  Json,
+  /// `key=value` pairs, one record per line.
+  Logfmt,
+  /// GELF 1.1 messages, one per line, as the GELF output sends them.
+  Gelf,
+  /// One OTLP JSON export request per line, for the Collector's
+  /// `otlpjsonfile` receiver.
+  Otlp,
+  /// Elastic Common Schema JSON, one object per line.
+  Ecs,
+};
+
+/// How closed log files are compressed.
+enum class Compression {
+  None,
+  Gzip,
+  Zstd,
+};
+
+/// The [`Durability`] variants as seen from C; the interval travels separately.
+enum class DurabilityMode {
+  None,
+  FlushEveryBatch,
+  SyncInterval,
+  SyncProd,
+};
+
 enum class LevelFilter : uintptr_t {
   Off,
   Prod,
@@ -30,6 +66,124 @@ enum class LogLevel {
   Trace = 3,
 };
 
+/// Stable error codes returned by every exported function. Values never change
+/// once released; new variants are only ever appended.
+enum class LoggerErrorCode {
+  Ok = 0,
+  NullPointer = 1,
+  InvalidUtf8 = 2,
+  InteriorNul = 3,
+  Io = 4,
+  ThreadPool = 5,
+  ThreadId = 6,
+  LockPoisoned = 7,
+  InvalidLevel = 8,
+  AlreadyInitialized = 9,
+  Unsupported = 10,
+  InvalidConfig = 11,
+  InvalidData = 12,
+  Panic = 255,
+};
+
+/// How a route splits records across files.
+enum class RouteKind {
+  /// Every record in one file; `{level}` renders as `All`.
+  Combined,
+  /// One file per level, the historical layout.
+  PerLevel,
+  /// One file per level holding that level and everything more severe, so
+  /// the `Debug` file has both `Prod` and `Debug` records.
+  Cumulative,
+  /// One file per record source.
+  PerSource,
+};
+
+/// How often files are rolled over regardless of their size.
+enum class Schedule {
+  /// Mostly useful for tests.
+  Minutely,
+  Hourly,
+  Daily,
+  /// Weeks start on Monday.
+  Weekly,
+};
+
+/// Syslog facilities, numbered as RFC 5424 numbers them.
+enum class Facility {
+  Kern = 0,
+  User,
+  Mail,
+  Daemon,
+  Auth,
+  Syslog,
+  Lpr,
+  News,
+  Uucp,
+  Cron,
+  AuthPriv,
+  Ftp,
+  Ntp,
+  Security,
+  Console,
+  SolarisCron,
+  Local0,
+  Local1,
+  Local2,
+  Local3,
+  Local4,
+  Local5,
+  Local6,
+  Local7,
+};
+
+/// The message layout.
+enum class SyslogProtocol {
+  /// `<PRI>1 TIMESTAMP HOST APP PID MSGID [SD] MSG`, with the source as
+  /// MSGID and key-value pairs as structured data.
+  Rfc5424,
+  /// The older BSD layout, `<PRI>Mmm dd hh:mm:ss HOST APP[PID]: MSG`, for
+  /// daemons that predate RFC 5424. Times are in the logger's time zone.
+  Rfc3164,
+};
+
+/// The [`SyslogTransport`] variants as seen from C; the address travels separately.
+enum class SyslogTransportKind {
+  Unix,
+  Udp,
+  Tcp,
+};
+
+/// How records are delimited on the stream.
+enum class Framing {
+  /// One JSON object per line, as the `Json` file format writes them.
+  JsonLines,
+  /// A big-endian `u32` length, then the record in the binary format's
+  /// record layout with its source inline.
+  LengthPrefixed,
+};
+
+/// The [`NetworkTarget`] variants as seen from C; the address travels separately.
+enum class NetworkTargetKind {
+  Tcp,
+  Unix,
+};
+
+/// The [`GelfTransport`] variants as seen from C; the address travels separately.
+enum class GelfTransportKind {
+  Udp,
+  Tcp,
+};
+
+/// How a batch is laid out in the request body.
+enum class BatchEncoding {
+  /// One JSON array of records, `application/json`.
+  JsonArray,
+  /// One JSON record per line, `application/x-ndjson`.
+  Ndjson,
+  /// An OTLP/HTTP JSON export request, for a `/v1/logs` endpoint.
+  Otlp,
+};
+
 struct AtomicUsize;
 
 struct LogData {
@@ -41,28 +195,229 @@ struct LogData {
 struct FfiStringResult {
   const char *result;
   const char *error;
+  LoggerErrorCode code;
 };
 
 extern const AtomicUsize STATE;
 
 extern const AtomicUsize MAX_LOG_LEVEL_FILTER;
 
-void start_logging();
+LoggerErrorCode start_logging();
 
-void log_message(LogData log_data);
+LoggerErrorCode log_message(LogData log_data);
 
-void log_trace(const char *source, const char *message);
+LoggerErrorCode log_trace(const char *source, const char *message);
 
-void log_debug(const char *source, const char *message);
+LoggerErrorCode log_debug(const char *source, const char *message);
 
-void log_prod(const char *source, const char *message);
+LoggerErrorCode log_prod(const char *source, const char *message);
 
 FfiStringResult read_logs(const char *file_path);
 
-void set_log_level(LogLevel log_level);
+/// Decodes a flight recorder file, oldest record first, one record per line.
+FfiStringResult read_flight_recorder(const char *file_path);
+
+/// Decodes a binary log file, compressed or not, to text or to JSON Lines.
+FfiStringResult read_binary_log(const char *file_path, bool json);
+
+LoggerErrorCode set_log_level(LogLevel log_level);
+
+/// Sets the directory log files are written to, creating it if missing.
+LoggerErrorCode set_log_directory(const char *directory);
+
+/// Sets the file name template, e.g. `{date}_{level}.log` (the default) or
+/// `{hostname}-{pid}-{source}-{date}T{hour}.log`.
+LoggerErrorCode set_file_name_pattern(const char *pattern);
+
+/// Rotates files once they would grow past `max_bytes`, keeping `max_backups`
+/// numbered backups. A `max_bytes` of 0 turns size-based rotation off.
+LoggerErrorCode set_rotation_size(uint64_t max_bytes, uintptr_t max_backups);
+
+/// Replaces all routing rules with a single route of `kind`.
+LoggerErrorCode set_routing(RouteKind kind);
+
+/// Adds a route, so matching records are written to its files as well.
+/// `pattern` may be null to use the route's default file name pattern.
+LoggerErrorCode add_route(RouteKind kind, const char *pattern, LogLevel max_level);
+
+/// Removes every route; nothing is written to files until one is added.
+LoggerErrorCode clear_routes();
+
+/// Rolls files over at every boundary of `schedule`, on top of any size limit.
+/// Weekly rotation needs a file name pattern with `{week}` instead of
+/// `{date}`, set first.
+LoggerErrorCode set_rotation_schedule(Schedule schedule);
+
+LoggerErrorCode clear_rotation_schedule();
+
+/// Sets the time zone for file names and rotation boundaries: `utc`, `local`
+/// or a fixed offset such as `+02:00`.
+LoggerErrorCode set_log_timezone(const char *timezone);
+
+/// Compresses rotated files, and files closed at a schedule boundary, in the background.
+LoggerErrorCode set_compression(Compression compression);
+
+/// Limits what old output is kept in the log directory: files older than
+/// `max_age_secs`, more than `max_files` files, or more than `max_total_bytes`
+/// in total are deleted, oldest first. 0 means no limit.
+LoggerErrorCode set_retention(uint64_t max_age_secs, uintptr_t max_files, uint64_t max_total_bytes);
+
+/// Rotates every open log file now, for logrotate-style tooling.
+LoggerErrorCode rotate_logs();
+
+LoggerErrorCode flush_logger();
+
+/// Like `flush_logger`, but also waits (`fdatasync`) until every open file
+/// has reached the disk.
+LoggerErrorCode flush_logger_sync();
+
+/// Sets the durability of every route's files, routes added later included.
+/// `interval_ms` is only used by `DurabilityMode::SyncInterval`.
+LoggerErrorCode set_durability(DurabilityMode mode, uint64_t interval_ms);
+
+/// Coordinates with other processes writing the same log directory, through
+/// a lock file in it, so records never interleave and a file is rotated once.
+LoggerErrorCode set_multi_process(bool enabled);
+
+/// Spools records to disk once `threshold` are waiting to be written; 0 keeps
+/// them all in memory.
+LoggerErrorCode set_spool_threshold(uintptr_t threshold);
+
+/// Keeps the last `capacity_bytes` of records down to `max_level` in a
+/// memory-mapped ring file at `path`, readable after a crash with
+/// `read_flight_recorder`. A null `path` switches the recorder off.
+LoggerErrorCode set_flight_recorder(const char *path, uint64_t capacity_bytes, LogLevel max_level);
+
+/// Chooses how records are laid out in log files.
+LoggerErrorCode set_log_format(LogFormat format);
+
+/// Chooses how records are printed to the console; `Binary` is refused.
+LoggerErrorCode set_console_format(LogFormat format);
+
+/// Lays out text file lines with `template`, compiled once here; null goes
+/// back to the built-in layout.
+LoggerErrorCode set_log_template(const char *template_);
+
+/// Lays out text console lines with `template`; null goes back to the
+/// built-in, colored layout.
+LoggerErrorCode set_console_template(const char *template_);
+
+/// Names the service, its version and the host in ECS lines; null keeps
+/// the current value, and an empty version leaves the version out.
+LoggerErrorCode set_service_info(const char *name, const char *version, const char *host);
+
+/// Also sends records down to `max_level` to a syslog daemon. `address` is a
+/// socket path for `Unix`, where null means `/dev/log`, and `host:port` for
+/// `Udp` and `Tcp`; a null `app_name` uses the executable's name.
+LoggerErrorCode set_syslog(SyslogTransportKind kind,
+                           const char *address,
+                           Facility facility,
+                           const char *app_name,
+                           SyslogProtocol protocol,
+                           LogLevel max_level);
+
+/// Stops sending records to syslog.
+LoggerErrorCode clear_syslog();
+
+/// Also sends records down to `max_level` to the systemd journal, as
+/// structured fields. A null `socket` means `/run/systemd/journal/socket`, a
+/// null `identifier` the executable's name.
+LoggerErrorCode set_journald(const char *socket, const char *identifier, LogLevel max_level);
+
+/// Stops sending records to the journal.
+LoggerErrorCode clear_journald();
+
+/// Also streams records down to `max_level` to `address`, `host:port` for
+/// `Tcp` and a socket path for `Unix`, from a thread of its own. While the
+/// peer is unreachable up to `queue_capacity` records are kept, 0 meaning the
+/// default of 10000, and the connection is retried with backoff.
+LoggerErrorCode set_network_output(NetworkTargetKind kind,
+                                   const char *address,
+                                   Framing framing,
+                                   uintptr_t queue_capacity,
+                                   LogLevel max_level);
+
+/// Stops streaming records, dropping any still queued.
+LoggerErrorCode clear_network_output();
+
+/// Also POSTs records down to `max_level` to `url` in batches of at most
+/// `max_records` records or `max_bytes`, sent at the latest `max_delay_ms`
+/// after their first record; 0 keeps the default of each. Headers are added
+/// with `add_http_header`.
+LoggerErrorCode set_http_output(const char *url,
+                                BatchEncoding encoding,
+                                bool gzip,
+                                uintptr_t max_records,
+                                uintptr_t max_bytes,
+                                uint64_t max_delay_ms,
+                                LogLevel max_level);
+
+/// Sends `name: value` with every request of the HTTP output, e.g. an
+/// `Authorization` token.
+LoggerErrorCode add_http_header(const char *name, const char *value);
+
+/// Stops posting records, after sending the batches still pending.
+LoggerErrorCode clear_http_output();
+
+/// Also sends records down to `max_level` to a Graylog input at `address`,
+/// `host:port`, as GELF 1.1. Over `Udp` large messages are chunked and
+/// `compress` gzips them; over `Tcp` messages end in a null byte.
+LoggerErrorCode set_gelf_output(GelfTransportKind kind,
+                                const char *address,
+                                bool compress,
+                                LogLevel max_level);
+
+/// Stops sending records to Graylog.
+LoggerErrorCode clear_gelf_output();
+
+/// Also forwards records down to `max_level` to a Fluentd or Fluent Bit
+/// `forward` input at `address`, `host:port`, tagged `tag_prefix.source`;
+/// a null prefix keeps the default of `common_logger`. With `require_ack`
+/// every message is sent again until the agent acknowledges it.
+LoggerErrorCode set_fluentd_output(const char *address,
+                                   const char *tag_prefix,
+                                   bool require_ack,
+                                   LogLevel max_level);
+
+/// Stops forwarding records, after sending those still queued.
+LoggerErrorCode clear_fluentd_output();
+
+/// Also pushes records down to `max_level` to the Grafana Loki at `url`.
+/// `label_fields` is a comma-separated list of the record fields streams
+/// are labeled by, e.g. `level,source`, the default kept for null; `tenant`,
+/// if not null, is sent as `X-Scope-OrgID`. Fixed labels are added with
+/// `add_loki_label`.
+LoggerErrorCode set_loki_output(const char *url,
+                                const char *label_fields,
+                                const char *tenant,
+                                LogLevel max_level);
+
+/// Labels every stream the Loki output pushes `name="value"`, e.g. `job`.
+LoggerErrorCode add_loki_label(const char *name, const char *value);
+
+/// Stops pushing records to Loki, after sending the batches still pending.
+LoggerErrorCode clear_loki_output();
+
+/// Also hands records down to `max_level` to the `ul-collectord` daemon
+/// listening on the Unix socket at `socket`, which adds this process's pid
+/// and `client_name` to each; a null name keeps the executable's name.
+LoggerErrorCode set_remote_output(const char *socket,
+                                  const char *client_name,
+                                  LogLevel max_level);
+
+/// Stops handing records to the collector, dropping those still queued.
+LoggerErrorCode clear_remote_output();
+
+/// The logger's current numbers as a JSON object: pending records and, once
+/// used, the network, HTTP, Fluentd, Loki and remote outputs' queues and counters,
+/// and how many records journald did not take.
+FfiStringResult logger_stats();
 
-void flush_logger();
+/// Replaces the whole configuration with the one in the JSON file at `path`.
+LoggerErrorCode load_config_file(const char *path);
 
-void cleanup_logger();
+/// Writes what is queued, then stops the network, HTTP, Fluentd, Loki and
+/// remote senders, each given two seconds to send what it still holds.
+LoggerErrorCode cleanup_logger();
 
 } // extern "C"
@@ -0,0 +1,31 @@
+use common_logger::models::compression::open_reader;
+use common_logger::models::decode_binary_log;
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (json, path) = match args.as_slice() {
+        [flag, path] if flag == "--json" => (true, path),
+        [path] => (false, path),
+        _ => {
+            eprintln!("usage: ul-binlog-decode [--json] <log-file>");
+            return ExitCode::from(2);
+        }
+    };
+    match open_reader(Path::new(path)).and_then(|reader| decode_binary_log(reader, json)) {
+        Ok(logs) => {
+            if !logs.is_empty() {
+                // A closed pipe, e.g. into `head`, is not worth reporting.
+                let _ = writeln!(io::stdout().lock(), "{}", logs);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("ERROR::DECODING BINARY LOG {}: {}", path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
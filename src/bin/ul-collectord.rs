@@ -0,0 +1,70 @@
+use common_logger::models::config::{set_config, LoggerConfig};
+use common_logger::models::error::LoggerErrorCode;
+use common_logger::models::remote::{listen, serve};
+use common_logger::models::{cleanup_logger, start_logging};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+
+/// SIGINT and SIGTERM, blocked in every thread so only [`wait_for_signal`] sees them.
+fn termination_signals() -> libc::sigset_t {
+    unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        signals
+    }
+}
+
+/// Writes what the clients sent before the signal, then leaves without the socket file.
+fn wait_for_signal(signals: libc::sigset_t, socket: PathBuf) {
+    let mut signal = 0;
+    unsafe { libc::sigwait(&signals, &mut signal) };
+    cleanup_logger();
+    let _ = std::fs::remove_file(&socket);
+    std::process::exit(0);
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (config, socket) = match args.as_slice() {
+        [flag, config, socket] if flag == "--config" => (Some(config), socket),
+        [socket] => (None, socket),
+        _ => {
+            eprintln!("usage: ul-collectord [--config <file>] <socket>");
+            return ExitCode::from(2);
+        }
+    };
+    if let Some(path) = config {
+        if let Err(e) = LoggerConfig::from_file(Path::new(path)).and_then(set_config) {
+            eprintln!("ERROR::LOADING CONFIG {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Before any thread starts, so every thread inherits the mask.
+    let signals = termination_signals();
+    unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) };
+    if start_logging() != LoggerErrorCode::Ok {
+        return ExitCode::FAILURE;
+    }
+    let listener = match listen(Path::new(socket)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("ERROR::LISTENING ON {}: {}", socket, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let path = PathBuf::from(socket);
+    thread::spawn(move || wait_for_signal(signals, path));
+
+    match serve(listener) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ERROR::SERVING {}: {}", socket, e);
+            ExitCode::FAILURE
+        }
+    }
+}
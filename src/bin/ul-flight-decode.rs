@@ -0,0 +1,27 @@
+use common_logger::models::flight_recorder;
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let [_, path] = args.as_slice() else {
+        eprintln!("usage: ul-flight-decode <ring-file>");
+        return ExitCode::from(2);
+    };
+    let records = match flight_recorder::decode(Path::new(path)) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("ERROR::DECODING FLIGHT RECORDER {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut out = io::stdout().lock();
+    for record in records {
+        if writeln!(out, "{}", record).is_err() {
+            break;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -12,7 +12,10 @@ use std::time::Duration;
 fn main() {
     // Initialize the async logger and the thread pool
     let async_logger = AsyncLogger::new();
-    AsyncLogger::start_background_task();
+    if let Err(e) = AsyncLogger::start_background_task() {
+        eprintln!("{}", e);
+        return;
+    }
 
     // This Arc allows multiple threads to hold a reference to the logger
     let logger = Arc::new(async_logger);
@@ -61,7 +64,9 @@ fn main() {
     thread::sleep(Duration::from_secs(10));
 
     for handle in handles {
-        handle.join().unwrap();
+        if handle.join().is_err() {
+            eprintln!("ERROR::CLIENT THREAD PANICKED");
+        }
     }
 
 
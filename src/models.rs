@@ -1,9 +1,41 @@
+pub mod binary;
+pub mod compression;
+pub mod config;
+pub mod config_file;
+pub(crate) mod connection;
 pub mod constants;
+pub mod durability;
+pub mod ecs;
+pub mod error;
+pub mod flight_recorder;
+pub mod fluentd;
+pub mod format;
+pub mod gelf;
+pub mod http;
+pub mod journald;
 pub mod level;
 pub mod log;
+pub mod logfmt;
+pub mod loki;
 pub mod metadata;
+pub mod msgpack;
+pub mod naming;
+pub mod network;
+pub mod otel;
+pub(crate) mod process_lock;
 pub mod record;
+pub mod remote;
+pub mod retention;
+pub mod rotation;
+pub mod routing;
+pub mod schedule;
+pub(crate) mod sender;
+pub mod service;
+pub mod spool;
+pub mod stats;
 pub mod string_handle;
+pub mod syslog;
+pub mod template;
 pub(crate) mod threads;
 mod utilities;
 
@@ -13,17 +45,30 @@ pub mod macros;
 
 
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::{BufReader, Write};
-use std::io::Error as E;
-use std::io::{BufRead, Read};
+use std::io::Read;
 use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::time::Duration;
 use crate::log;
-use crate::models::constants::LOGGING_THREAD_TIMEOUT;
+use crate::models::compression::{open_reader, Compression};
+use crate::models::config::{config, set_config, update_config, LoggerConfig};
+use crate::models::error::{ffi_guard, LoggerError, LoggerErrorCode};
+use crate::models::flight_recorder::FlightRecorder;
+use crate::models::fluentd::FluentdOutput;
+use crate::models::format::{json_line, text_line, LogFormat};
+use crate::models::durability::{Durability, DurabilityMode};
+use crate::models::journald::JournaldOutput;
+use crate::models::gelf::{GelfOutput, GelfTransport, GelfTransportKind};
+use crate::models::http::{BatchEncoding, HttpOutput};
 use crate::models::level::Level;
-use crate::models::log::{Log, logger};
+use crate::models::loki::{LabelField, LokiOutput};
+use crate::models::network::{Framing, NetworkOutput, NetworkTarget, NetworkTargetKind};
+use crate::models::remote::RemoteOutput;
+use crate::models::log::logger;
+use crate::models::routing::{Route, RouteKind};
+use crate::models::schedule::{LogTimeZone, Schedule};
+use crate::models::syslog::{Facility, SyslogOutput, SyslogProtocol, SyslogTransport, SyslogTransportKind};
 use crate::models::logger::{AsyncLogger, set_max_log_level};
 
 #[repr(C)]
@@ -45,6 +90,7 @@ pub enum LogLevel {
 pub struct FfiStringResult {
     pub result: *const c_char,
     pub error: *const c_char,
+    pub code: LoggerErrorCode,
 }
 
 #[repr(C)]
@@ -54,105 +100,856 @@ pub struct LogData {
     source: *const c_char,
 }
 
-#[no_mangle]
-pub extern "C" fn start_logging() {
-    AsyncLogger::start_background_task();
+/// Borrows a caller-owned C string, rejecting null pointers. Invalid UTF-8 is
+/// replaced rather than refused, matching what the logging calls always did.
+///
+/// # Safety
+/// `ptr` must be null or point to a valid NUL-terminated string.
+unsafe fn c_str_lossy(ptr: *const c_char) -> Result<String, LoggerError> {
+    if ptr.is_null() {
+        return Err(LoggerError::NullPointer);
+    }
+    Ok(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// Same as [`c_str_lossy`] but insists on valid UTF-8, for paths and settings.
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string that outlives `'a`.
+unsafe fn c_str<'a>(ptr: *const c_char) -> Result<&'a str, LoggerError> {
+    if ptr.is_null() {
+        return Err(LoggerError::NullPointer);
+    }
+    Ok(CStr::from_ptr(ptr).to_str()?)
 }
-#[no_mangle]
-pub extern "C" fn log_message(log_data: LogData) {
-    let args = unsafe { CString::from_raw(log_data.args as *mut _) }.to_string_lossy().into_owned();
-    let source = unsafe { CString::from_raw(log_data.source as *mut _) }.to_string_lossy().into_owned();
 
-    let log_level = match log_data.level {
+fn to_level(log_level: LogLevel) -> Level {
+    match log_level {
         LogLevel::Trace => Level::Trace,
         LogLevel::Debug => Level::Debug,
         LogLevel::Prod => Level::Prod,
         LogLevel::None => Level::Prod
-    };
+    }
+}
 
-    log!(source: &source.clone(), log_level, args.clone());
+#[no_mangle]
+pub extern "C" fn start_logging() -> LoggerErrorCode {
+    ffi_guard(AsyncLogger::start_background_task)
 }
 
+/// # Safety
+///
+/// `log_data.args` and `log_data.source` must each be null or point to a
+/// NUL-terminated string that stays valid during the call.
 #[no_mangle]
-pub extern "C" fn log_trace(source: *const c_char, message: *const c_char) {
-    let string_source = unsafe { CStr::from_ptr(source) }.to_string_lossy().into_owned();
-    let string_message = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+pub unsafe extern "C" fn log_message(log_data: LogData) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let args = unsafe { c_str_lossy(log_data.args) }?;
+        let source = unsafe { c_str_lossy(log_data.source) }?;
 
-    log!(source: &string_source.clone(), Level::Trace, &string_message.clone());
+        log!(source: &source, to_level(log_data.level), args);
+        Ok(())
+    })
 }
 
+/// # Safety
+///
+/// `source` and `message` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
 #[no_mangle]
-pub extern "C" fn log_debug(source: *const c_char, message: *const c_char) {
-    let string_source = unsafe { CStr::from_ptr(source) }.to_string_lossy().into_owned();
-    let string_message = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+pub unsafe extern "C" fn log_trace(source: *const c_char, message: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let string_source = unsafe { c_str_lossy(source) }?;
+        let string_message = unsafe { c_str_lossy(message) }?;
 
-    log!(source: &string_source.clone(), Level::Debug, &string_message.clone());
+        log!(source: &string_source, Level::Trace, &string_message);
+        Ok(())
+    })
 }
 
+/// # Safety
+///
+/// `source` and `message` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
 #[no_mangle]
-pub extern "C" fn log_prod(source: *const c_char, message: *const c_char) {
-    let string_source = unsafe { CStr::from_ptr(source) }.to_string_lossy().into_owned();
-    let string_message = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+pub unsafe extern "C" fn log_debug(source: *const c_char, message: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let string_source = unsafe { c_str_lossy(source) }?;
+        let string_message = unsafe { c_str_lossy(message) }?;
 
-    log!(source: &string_source.clone(), Level::Prod, &string_message.clone());
+        log!(source: &string_source, Level::Debug, &string_message);
+        Ok(())
+    })
 }
 
+/// # Safety
+///
+/// `source` and `message` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
 #[no_mangle]
-pub extern "C" fn read_logs(file_path: *const c_char) -> FfiStringResult {
-    let file_path_str = unsafe { CStr::from_ptr(file_path) }
-        .to_str()
-        .expect("ERROR::INVALID UTF8");
-    let file_path = Path::new(file_path_str);
+pub unsafe extern "C" fn log_prod(source: *const c_char, message: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let string_source = unsafe { c_str_lossy(source) }?;
+        let string_message = unsafe { c_str_lossy(message) }?;
 
-    let mut result = FfiStringResult {
-        result: std::ptr::null(),
-        error: std::ptr::null(),
-    };
+        log!(source: &string_source, Level::Prod, &string_message);
+        Ok(())
+    })
+}
 
-    if let Ok(file) = File::open(file_path) {
-        let reader = BufReader::new(file);
-        let logs: Vec<String> = reader
+/// Reads a whole log file into a string; `.gz` and `.zst` files are decompressed.
+pub fn read_log_file(file_path: &Path) -> Result<String, LoggerError> {
+    let mut reader = open_reader(file_path)?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if binary::is_binary(&bytes) {
+        return decode_binary_log(bytes.as_slice(), false);
+    }
+    let logs = String::from_utf8(bytes).map_err(|e| e.utf8_error())?;
+    if logfmt::is_logfmt(&logs) {
+        // Shown like every other file; lines that do not parse are kept as they are.
+        let timezone = config().timezone;
+        let lines: Vec<String> = logs
             .lines()
-            .collect::<Result<_, E>>()
-            .unwrap_or_else(|e| {
-                result.error = CString::new(e.to_string())
-                    .expect("ERROR::CSTRING CONVERSION FAILED")
-                    .into_raw();
-                Vec::new()
-            });
-        let logs_str = logs.join("\n");
-        result.result = CString::new(logs_str)
-            .expect("ERROR::CSTRING CONVERSION FAILED")
-            .into_raw();
-    } else {
-        result.error = CString::new("ERROR::FAILED TO OPEN FILE")
-            .expect("ERROR::CSTRING CONVERSION FAILED")
-            .into_raw();
+            .map(|line| match logfmt::parse_record(line) {
+                Ok(record) => text_line(&record, timezone),
+                Err(_) => line.to_string(),
+            })
+            .collect();
+        return Ok(lines.join("\n"));
     }
+    Ok(logs.lines().collect::<Vec<_>>().join("\n"))
+}
+
+/// Decodes a binary log into text lines, or into one JSON object per line.
+pub fn decode_binary_log<R: Read>(reader: R, json: bool) -> Result<String, LoggerError> {
+    let timezone = config().timezone;
+    let lines: Vec<String> = binary::decode(reader)?
+        .iter()
+        .map(|record| match json {
+            true => json_line(record),
+            false => text_line(record, timezone),
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+fn into_c_string(s: String) -> *const c_char {
+    // Interior NULs cannot cross into C; replace them rather than failing the whole read.
+    CString::new(s.replace('\0', " "))
+        .map(|c| c.into_raw() as *const c_char)
+        .unwrap_or(std::ptr::null())
+}
 
-    result
+/// Runs `f` and hands its string, its error or a panic to C, so nothing
+/// ever unwinds across the `extern "C"` boundary.
+fn ffi_string_result(f: impl FnOnce() -> Result<String, LoggerError>) -> FfiStringResult {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(LoggerError::from_panic(payload)));
+    match outcome {
+        Ok(s) => FfiStringResult {
+            result: into_c_string(s),
+            error: std::ptr::null(),
+            code: LoggerErrorCode::Ok,
+        },
+        Err(e) => FfiStringResult {
+            result: std::ptr::null(),
+            error: into_c_string(e.to_string()),
+            code: e.code(),
+        },
+    }
 }
 
+/// # Safety
+///
+/// `file_path` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
 #[no_mangle]
-pub extern "C" fn set_log_level(log_level: LogLevel) {
-    let log_level = match log_level {
-        LogLevel::Trace => Level::Trace,
-        LogLevel::Debug => Level::Debug,
-        LogLevel::Prod => Level::Prod,
-        LogLevel::None => Level::Prod
-    };
+pub unsafe extern "C" fn read_logs(file_path: *const c_char) -> FfiStringResult {
+    ffi_string_result(|| {
+        let file_path_str = unsafe { c_str(file_path) }?;
+        read_log_file(Path::new(file_path_str))
+    })
+}
+
+/// Decodes a binary log file, compressed or not, to text or to JSON Lines.
+///
+/// # Safety
+///
+/// `file_path` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn read_binary_log(file_path: *const c_char, json: bool) -> FfiStringResult {
+    ffi_string_result(|| {
+        let reader = open_reader(Path::new(unsafe { c_str(file_path) }?))?;
+        decode_binary_log(reader, json)
+    })
+}
 
-    set_max_log_level(log_level);
+/// Decodes a flight recorder file, oldest record first, one record per line.
+///
+/// # Safety
+///
+/// `file_path` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn read_flight_recorder(file_path: *const c_char) -> FfiStringResult {
+    ffi_string_result(|| {
+        let records = flight_recorder::decode(Path::new(unsafe { c_str(file_path) }?))?;
+        Ok(records.iter().map(|record| format!("{}\n", record)).collect::<String>())
+    })
 }
 
+#[no_mangle]
+pub extern "C" fn set_log_level(log_level: LogLevel) -> LoggerErrorCode {
+    ffi_guard(|| {
+        set_max_log_level(to_level(log_level));
+        Ok(())
+    })
+}
 
+/// Sets the directory log files are written to, creating it if missing.
+///
+/// # Safety
+///
+/// `directory` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
 #[no_mangle]
-pub extern "C" fn flush_logger() {
-    logger().flush();
+pub unsafe extern "C" fn set_log_directory(directory: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let directory = unsafe { c_str(directory) }?;
+        update_config(|config| config.directory(directory))
+    })
 }
 
+/// Sets the file name template, e.g. `{date}_{level}.log` (the default) or
+/// `{hostname}-{pid}-{source}-{date}T{hour}.log`.
+///
+/// # Safety
+///
+/// `pattern` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
 #[no_mangle]
-pub extern "C" fn cleanup_logger() {
-    std::thread::sleep(Duration::from_secs(LOGGING_THREAD_TIMEOUT));
+pub unsafe extern "C" fn set_file_name_pattern(pattern: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let pattern = unsafe { c_str(pattern) }?;
+        update_config(|config| config.file_name_pattern(pattern))
+    })
 }
 
+/// Rotates files once they would grow past `max_bytes`, keeping `max_backups`
+/// numbered backups. A `max_bytes` of 0 turns size-based rotation off.
+#[no_mangle]
+pub extern "C" fn set_rotation_size(max_bytes: u64, max_backups: usize) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let max_bytes = if max_bytes == 0 { None } else { Some(max_bytes) };
+        update_config(|config| config.max_file_size(max_bytes).max_backups(max_backups))
+    })
+}
+
+/// Replaces all routing rules with a single route of `kind`.
+#[no_mangle]
+pub extern "C" fn set_routing(kind: RouteKind) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.routes(vec![Route::new(kind)])))
+}
+
+/// Adds a route, so matching records are written to its files as well.
+/// `pattern` may be null to use the route's default file name pattern.
+///
+/// # Safety
+///
+/// `pattern` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn add_route(kind: RouteKind, pattern: *const c_char, max_level: LogLevel) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut route = Route::new(kind).max_level(to_level(max_level).to_level_filter());
+        if !pattern.is_null() {
+            route = route.pattern(unsafe { c_str(pattern) }?)?;
+        }
+        update_config(|config| config.route(route))
+    })
+}
+
+/// Removes every route; nothing is written to files until one is added.
+#[no_mangle]
+pub extern "C" fn clear_routes() -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.routes(Vec::new())))
+}
+
+/// Rolls files over at every boundary of `schedule`, on top of any size limit.
+/// Weekly rotation needs a file name pattern with `{week}` instead of
+/// `{date}`, set first.
+#[no_mangle]
+pub extern "C" fn set_rotation_schedule(schedule: Schedule) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.rotation_schedule(Some(schedule))))
+}
+
+#[no_mangle]
+pub extern "C" fn clear_rotation_schedule() -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.rotation_schedule(None)))
+}
+
+/// Sets the time zone for file names and rotation boundaries: `utc`, `local`
+/// or a fixed offset such as `+02:00`.
+///
+/// # Safety
+///
+/// `timezone` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_log_timezone(timezone: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let timezone: LogTimeZone = unsafe { c_str(timezone) }?.parse()?;
+        update_config(|config| config.timezone(timezone))
+    })
+}
+
+/// Compresses rotated files, and files closed at a schedule boundary, in the background.
+#[no_mangle]
+pub extern "C" fn set_compression(compression: Compression) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.compression(compression)))
+}
+
+/// Limits what old output is kept in the log directory: files older than
+/// `max_age_secs`, more than `max_files` files, or more than `max_total_bytes`
+/// in total are deleted, oldest first. 0 means no limit.
+#[no_mangle]
+pub extern "C" fn set_retention(max_age_secs: u64, max_files: usize, max_total_bytes: u64) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let max_age = Some(Duration::from_secs(max_age_secs)).filter(|age| !age.is_zero());
+        let max_files = Some(max_files).filter(|&files| files > 0);
+        let max_total_bytes = Some(max_total_bytes).filter(|&bytes| bytes > 0);
+        update_config(|config| config.max_age(max_age).max_files(max_files).max_total_size(max_total_bytes))?;
+        retention::request();
+        Ok(())
+    })
+}
+
+/// Rotates every open log file now, for logrotate-style tooling.
+#[no_mangle]
+pub extern "C" fn rotate_logs() -> LoggerErrorCode {
+    ffi_guard(|| rotation::rotate_all(&config().rotation))
+}
+
+#[no_mangle]
+pub extern "C" fn flush_logger() -> LoggerErrorCode {
+    ffi_guard(|| {
+        logger().flush();
+        rotation::flush_all(false)
+    })
+}
+
+/// Like `flush_logger`, but also waits (`fdatasync`) until every open file
+/// has reached the disk.
+#[no_mangle]
+pub extern "C" fn flush_logger_sync() -> LoggerErrorCode {
+    ffi_guard(|| {
+        logger().flush();
+        rotation::flush_all(true)
+    })
+}
+
+/// Sets the durability of every route's files, routes added later included.
+/// `interval_ms` is only used by `DurabilityMode::SyncInterval`.
+#[no_mangle]
+pub extern "C" fn set_durability(mode: DurabilityMode, interval_ms: u64) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let durability = Durability::from_mode(mode, Duration::from_millis(interval_ms))?;
+        update_config(|config| config.durability(durability))
+    })
+}
+
+/// Coordinates with other processes writing the same log directory, through
+/// a lock file in it, so records never interleave and a file is rotated once.
+#[no_mangle]
+pub extern "C" fn set_multi_process(enabled: bool) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.multi_process(enabled)))
+}
+
+/// Spools records to disk once `threshold` are waiting to be written; 0 keeps
+/// them all in memory.
+#[no_mangle]
+pub extern "C" fn set_spool_threshold(threshold: usize) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.spool(Some(threshold).filter(|&threshold| threshold > 0))))
+}
+
+/// Keeps the last `capacity_bytes` of records down to `max_level` in a
+/// memory-mapped ring file at `path`, readable after a crash with
+/// `read_flight_recorder`. A null `path` switches the recorder off.
+///
+/// # Safety
+///
+/// `path` must be null or point to a NUL-terminated string that stays valid
+/// during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_flight_recorder(path: *const c_char, capacity_bytes: u64, max_level: LogLevel) -> LoggerErrorCode {
+    ffi_guard(|| {
+        if path.is_null() {
+            update_config(|config| config.flight_recorder(None))?;
+            flight_recorder::close();
+            return Ok(());
+        }
+        let recorder = FlightRecorder::new(unsafe { c_str(path) }?, capacity_bytes)
+            .max_level(to_level(max_level).to_level_filter());
+        update_config(|config| config.flight_recorder(Some(recorder)))
+    })
+}
+
+/// Chooses how records are laid out in log files.
+#[no_mangle]
+pub extern "C" fn set_log_format(format: LogFormat) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.format(format)))
+}
+
+/// Chooses how records are printed to the console; `Binary` is refused.
+#[no_mangle]
+pub extern "C" fn set_console_format(format: LogFormat) -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.console_format(format)))
+}
+
+/// Lays out text file lines with `template`, compiled once here; null goes
+/// back to the built-in layout.
+///
+/// # Safety
+///
+/// `template` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_log_template(template: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let template = match template.is_null() {
+            true => None,
+            false => Some(unsafe { c_str(template) }?.parse()?),
+        };
+        update_config(|config| config.template(template))
+    })
+}
+
+/// Lays out text console lines with `template`; null goes back to the
+/// built-in, colored layout.
+///
+/// # Safety
+///
+/// `template` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_console_template(template: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let template = match template.is_null() {
+            true => None,
+            false => Some(unsafe { c_str(template) }?.parse()?),
+        };
+        update_config(|config| config.console_template(template))
+    })
+}
+
+/// Names the service, its version and the host in ECS lines; null keeps
+/// the current value, and an empty version leaves the version out.
+///
+/// # Safety
+///
+/// `name`, `version` and `host` must each be null or point to a NUL-
+/// terminated string that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_service_info(
+    name: *const c_char,
+    version: *const c_char,
+    host: *const c_char,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let name = match name.is_null() {
+            true => None,
+            false => Some(unsafe { c_str(name) }?),
+        };
+        let version = match version.is_null() {
+            true => None,
+            false => Some(unsafe { c_str(version) }?),
+        };
+        let host = match host.is_null() {
+            true => None,
+            false => Some(unsafe { c_str(host) }?),
+        };
+        update_config(|config| {
+            if let Some(name) = name {
+                config.service_name(name);
+            }
+            if let Some(host) = host {
+                config.host_name(host);
+            }
+            if let Some(version) = version {
+                config.service_version(Some(version).filter(|version| !version.is_empty()));
+            }
+            config
+        })
+    })
+}
+
+/// Also sends records down to `max_level` to a syslog daemon. `address` is a
+/// socket path for `Unix`, where null means `/dev/log`, and `host:port` for
+/// `Udp` and `Tcp`; a null `app_name` uses the executable's name.
+///
+/// # Safety
+///
+/// `address` and `app_name` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_syslog(
+    kind: SyslogTransportKind,
+    address: *const c_char,
+    facility: Facility,
+    app_name: *const c_char,
+    protocol: SyslogProtocol,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let address = match address.is_null() {
+            true => "",
+            false => unsafe { c_str(address) }?,
+        };
+        let mut output = SyslogOutput::new(SyslogTransport::new(kind, address)?)
+            .facility(facility)
+            .protocol(protocol)
+            .max_level(to_level(max_level).to_level_filter());
+        if !app_name.is_null() {
+            output = output.app_name(unsafe { c_str(app_name) }?);
+        }
+        update_config(|config| config.syslog(Some(output)))
+    })
+}
+
+/// Stops sending records to syslog.
+#[no_mangle]
+pub extern "C" fn clear_syslog() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.syslog(None))?;
+        syslog::close();
+        Ok(())
+    })
+}
+
+/// Also sends records down to `max_level` to the systemd journal, as
+/// structured fields. A null `socket` means `/run/systemd/journal/socket`, a
+/// null `identifier` the executable's name.
+///
+/// # Safety
+///
+/// `socket` and `identifier` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_journald(socket: *const c_char, identifier: *const c_char, max_level: LogLevel) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut output = JournaldOutput::new().max_level(to_level(max_level).to_level_filter());
+        if !socket.is_null() {
+            output = output.socket(unsafe { c_str(socket) }?);
+        }
+        if !identifier.is_null() {
+            output = output.identifier(unsafe { c_str(identifier) }?);
+        }
+        update_config(|config| config.journald(Some(output)))
+    })
+}
+
+/// Stops sending records to the journal.
+#[no_mangle]
+pub extern "C" fn clear_journald() -> LoggerErrorCode {
+    ffi_guard(|| update_config(|config| config.journald(None)))
+}
+
+/// Also streams records down to `max_level` to `address`, `host:port` for
+/// `Tcp` and a socket path for `Unix`, from a thread of its own. While the
+/// peer is unreachable up to `queue_capacity` records are kept, 0 meaning the
+/// default of 10000, and the connection is retried with backoff.
+///
+/// # Safety
+///
+/// `address` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_network_output(
+    kind: NetworkTargetKind,
+    address: *const c_char,
+    framing: Framing,
+    queue_capacity: usize,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut output = NetworkOutput::new(NetworkTarget::new(kind, unsafe { c_str(address) }?)?)
+            .framing(framing)
+            .max_level(to_level(max_level).to_level_filter());
+        if queue_capacity > 0 {
+            output = output.queue_capacity(queue_capacity);
+        }
+        update_config(|config| config.network(Some(output)))
+    })
+}
+
+/// Stops streaming records, dropping any still queued.
+#[no_mangle]
+pub extern "C" fn clear_network_output() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.network(None))?;
+        network::close();
+        Ok(())
+    })
+}
+
+/// Also POSTs records down to `max_level` to `url` in batches of at most
+/// `max_records` records or `max_bytes`, sent at the latest `max_delay_ms`
+/// after their first record; 0 keeps the default of each. Headers are added
+/// with `add_http_header`.
+///
+/// # Safety
+///
+/// `url` must be null or point to a NUL-terminated string that stays valid
+/// during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_http_output(
+    url: *const c_char,
+    encoding: BatchEncoding,
+    gzip: bool,
+    max_records: usize,
+    max_bytes: usize,
+    max_delay_ms: u64,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut output = HttpOutput::new(unsafe { c_str(url) }?)
+            .encoding(encoding)
+            .gzip(gzip)
+            .max_level(to_level(max_level).to_level_filter());
+        if max_records > 0 {
+            output = output.max_records(max_records);
+        }
+        if max_bytes > 0 {
+            output = output.max_bytes(max_bytes);
+        }
+        if max_delay_ms > 0 {
+            output = output.max_delay(Duration::from_millis(max_delay_ms));
+        }
+        update_config(|config| config.http(Some(output)))
+    })
+}
+
+/// Sends `name: value` with every request of the HTTP output, e.g. an
+/// `Authorization` token.
+///
+/// # Safety
+///
+/// `name` and `value` must each be null or point to a NUL-terminated string
+/// that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn add_http_header(name: *const c_char, value: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let (name, value) = unsafe { (c_str(name)?, c_str(value)?) };
+        let output = config()
+            .http
+            .clone()
+            .ok_or_else(|| LoggerError::InvalidConfig("no HTTP output is configured".to_string()))?;
+        update_config(|config| config.http(Some(output.header(name, value))))
+    })
+}
+
+/// Stops posting records, after sending the batches still pending.
+#[no_mangle]
+pub extern "C" fn clear_http_output() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.http(None))?;
+        http::close();
+        Ok(())
+    })
+}
+
+/// Also sends records down to `max_level` to a Graylog input at `address`,
+/// `host:port`, as GELF 1.1. Over `Udp` large messages are chunked and
+/// `compress` gzips them; over `Tcp` messages end in a null byte.
+///
+/// # Safety
+///
+/// `address` must be null or point to a NUL-terminated string that stays
+/// valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_gelf_output(
+    kind: GelfTransportKind,
+    address: *const c_char,
+    compress: bool,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let output = GelfOutput::new(GelfTransport::new(kind, unsafe { c_str(address) }?)?)
+            .compress(compress)
+            .max_level(to_level(max_level).to_level_filter());
+        update_config(|config| config.gelf(Some(output)))
+    })
+}
+
+/// Stops sending records to Graylog.
+#[no_mangle]
+pub extern "C" fn clear_gelf_output() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.gelf(None))?;
+        gelf::close();
+        Ok(())
+    })
+}
+
+/// Also forwards records down to `max_level` to a Fluentd or Fluent Bit
+/// `forward` input at `address`, `host:port`, tagged `tag_prefix.source`;
+/// a null prefix keeps the default of `common_logger`. With `require_ack`
+/// every message is sent again until the agent acknowledges it.
+///
+/// # Safety
+///
+/// `address` and `tag_prefix` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_fluentd_output(
+    address: *const c_char,
+    tag_prefix: *const c_char,
+    require_ack: bool,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut output = FluentdOutput::new(unsafe { c_str(address) }?)
+            .require_ack(require_ack)
+            .max_level(to_level(max_level).to_level_filter());
+        if !tag_prefix.is_null() {
+            output = output.tag_prefix(unsafe { c_str(tag_prefix) }?);
+        }
+        update_config(|config| config.fluentd(Some(output)))
+    })
+}
+
+/// Stops forwarding records, after sending those still queued.
+#[no_mangle]
+pub extern "C" fn clear_fluentd_output() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.fluentd(None))?;
+        fluentd::close();
+        Ok(())
+    })
+}
+
+/// Also pushes records down to `max_level` to the Grafana Loki at `url`.
+/// `label_fields` is a comma-separated list of the record fields streams
+/// are labeled by, e.g. `level,source`, the default kept for null; `tenant`,
+/// if not null, is sent as `X-Scope-OrgID`. Fixed labels are added with
+/// `add_loki_label`.
+///
+/// # Safety
+///
+/// `url`, `label_fields` and `tenant` must each be null or point to a NUL-
+/// terminated string that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_loki_output(
+    url: *const c_char,
+    label_fields: *const c_char,
+    tenant: *const c_char,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut output = LokiOutput::new(unsafe { c_str(url) }?).max_level(to_level(max_level).to_level_filter());
+        if !label_fields.is_null() {
+            let fields = unsafe { c_str(label_fields) }?
+                .split(',')
+                .map(|field| field.trim().parse())
+                .collect::<Result<Vec<LabelField>, _>>()?;
+            output = output.label_fields(&fields);
+        }
+        if !tenant.is_null() {
+            output = output.tenant(unsafe { c_str(tenant) }?);
+        }
+        update_config(|config| config.loki(Some(output)))
+    })
+}
+
+/// Labels every stream the Loki output pushes `name="value"`, e.g. `job`.
+///
+/// # Safety
+///
+/// `name` and `value` must each be null or point to a NUL-terminated string
+/// that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn add_loki_label(name: *const c_char, value: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let (name, value) = unsafe { (c_str(name)?, c_str(value)?) };
+        let output = config()
+            .loki
+            .clone()
+            .ok_or_else(|| LoggerError::InvalidConfig("no Loki output is configured".to_string()))?;
+        update_config(|config| config.loki(Some(output.label(name, value))))
+    })
+}
+
+/// Stops pushing records to Loki, after sending the batches still pending.
+#[no_mangle]
+pub extern "C" fn clear_loki_output() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.loki(None))?;
+        loki::close();
+        Ok(())
+    })
+}
+
+/// Also hands records down to `max_level` to the `ul-collectord` daemon
+/// listening on the Unix socket at `socket`, which adds this process's pid
+/// and `client_name` to each; a null name keeps the executable's name.
+///
+/// # Safety
+///
+/// `socket` and `client_name` must each be null or point to a NUL-terminated
+/// string that stays valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn set_remote_output(
+    socket: *const c_char,
+    client_name: *const c_char,
+    max_level: LogLevel,
+) -> LoggerErrorCode {
+    ffi_guard(|| {
+        let mut output =
+            RemoteOutput::new(unsafe { c_str(socket) }?).max_level(to_level(max_level).to_level_filter());
+        if !client_name.is_null() {
+            output = output.client_name(unsafe { c_str(client_name) }?);
+        }
+        update_config(|config| config.remote(Some(output)))
+    })
+}
+
+/// Stops handing records to the collector, dropping those still queued.
+#[no_mangle]
+pub extern "C" fn clear_remote_output() -> LoggerErrorCode {
+    ffi_guard(|| {
+        update_config(|config| config.remote(None))?;
+        remote::close();
+        Ok(())
+    })
+}
+
+/// The logger's current numbers as a JSON object: pending records and, once
+/// used, the network, HTTP, Fluentd, Loki and remote outputs' queues and counters,
+/// and how many records journald did not take.
+#[no_mangle]
+pub extern "C" fn logger_stats() -> FfiStringResult {
+    ffi_string_result(|| Ok(stats::stats().to_json()))
+}
+
+/// Replaces the whole configuration with the one in the JSON file at `path`.
+///
+/// # Safety
+///
+/// `path` must be null or point to a NUL-terminated string that stays valid
+/// during the call.
+#[no_mangle]
+pub unsafe extern "C" fn load_config_file(path: *const c_char) -> LoggerErrorCode {
+    ffi_guard(|| set_config(LoggerConfig::from_file(Path::new(unsafe { c_str(path) }?))?))
+}
+
+#[no_mangle]
+pub extern "C" fn cleanup_logger() -> LoggerErrorCode {
+    // Waits, up to `LOGGING_THREAD_TIMEOUT`, for queued records, then stops
+    // the network, HTTP, Fluentd, Loki and remote senders, each given
+    // `OUTPUT_FLUSH_TIMEOUT` to send what it still holds.
+    ffi_guard(|| {
+        logger::shut_down();
+        Ok(())
+    })
+}
@@ -1,3 +1,4 @@
+use crate::models::error::LoggerError;
 use crate::models::level::Level;
 use crate::models::log::logger;
 use crate::models::metadata::LogInfo;
@@ -14,12 +15,8 @@ pub fn log(
     level: Level,
     &(source, module_path, file): &(&str, &'static str, &'static str),
     line: u32,
-    kvs: Option<&[(&str, &str)]>,
-) {
-    if kvs.is_some() {
-        panic!("ERROR OF KVS")
-    }
-
+    kvs: Option<&[(&str, String)]>,
+) -> Result<(), LoggerError> {
     logger().log(
         &LogRecordBuilder::new()
             .args(args.clone())
@@ -28,8 +25,10 @@ pub fn log(
             .module_path_static(Some(module_path))
             .file_static(Some(file))
             .line(Some(line))
+            .kvs(kvs.unwrap_or_default())
             .build(),
     );
+    Ok(())
 }
 
 
@@ -40,9 +39,9 @@ pub fn log_console(
     source: String,
     thread: u64,
     kvs: Option<&[(&str, &str)]>,
-) {
+) -> Result<(), LoggerError> {
     if kvs.is_some() {
-        panic!("ERROR OF KVS")
+        return Err(LoggerError::Unsupported("key-value pairs"));
     }
 
     let current_date = Utc::now();
@@ -64,6 +63,7 @@ pub fn log_console(
     );
 
     println!("{}", log_entry);
+    Ok(())
 }
 
 
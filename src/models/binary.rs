@@ -0,0 +1,263 @@
+use crate::models::error::LoggerError;
+use crate::models::level::Level;
+use crate::models::logger::LogData;
+use chrono::DateTime;
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+/// Every binary log file starts with these bytes followed by the version.
+pub const MAGIC: &[u8; 7] = b"ULOGBIN";
+pub const VERSION: u8 = 1;
+
+/// Frame types. Every frame is a varint length followed by that many bytes,
+/// the first of which is the type, so decoders can skip types they do not know.
+const FRAME_SOURCE: u8 = 0;
+const FRAME_RECORD: u8 = 1;
+
+/// Source references are `id << 1`; an odd tag means the name follows inline.
+const INLINE_SOURCE: u64 = 1;
+
+/// Frames larger than this are taken for a corrupt file.
+const MAX_FRAME_LEN: u64 = 64 * 1024 * 1024;
+
+/// Source names already defined in the current file, so later records can
+/// refer to them by number.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    sources: HashMap<String, u64>,
+}
+
+impl Interner {
+    pub(crate) fn clear(&mut self) {
+        self.sources.clear();
+    }
+}
+
+/// The versioned header a new binary file starts with.
+pub fn header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+    header
+}
+
+/// Whether `bytes`, the start of a file, look like a binary log.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_str(buf: &mut Vec<u8>, value: &str) {
+    put_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn frame(out: &mut Vec<u8>, kind: u8, body: &[u8]) {
+    put_varint(out, body.len() as u64 + 1);
+    out.push(kind);
+    out.extend_from_slice(body);
+}
+
+/// The fields of `record` without framing; the source is written inline
+/// unless `source_id` refers to an earlier definition.
+pub(crate) fn encode_body(record: &LogData, source_id: Option<u64>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(32 + record.source.len() + record.message.len());
+    put_varint(&mut buf, zigzag(record.time.timestamp_micros()));
+    buf.push(record.level as u8);
+    put_varint(&mut buf, record.sequence);
+    put_varint(&mut buf, record.thread);
+    put_str(&mut buf, record.thread_name.as_deref().unwrap_or_default());
+    match source_id {
+        Some(id) => put_varint(&mut buf, id << 1),
+        None => {
+            put_varint(&mut buf, INLINE_SOURCE);
+            put_str(&mut buf, &record.source);
+        }
+    }
+    put_str(&mut buf, record.module_path.as_deref().unwrap_or_default());
+    put_str(&mut buf, record.file.as_deref().unwrap_or_default());
+    put_varint(&mut buf, record.line.map_or(0, |line| line as u64 + 1));
+    put_str(&mut buf, &record.message);
+    put_varint(&mut buf, record.key_values.len() as u64);
+    for (key, value) in &record.key_values {
+        put_str(&mut buf, key);
+        put_str(&mut buf, value);
+    }
+    buf
+}
+
+/// `record` as frames. With an `interner` each source name is defined once
+/// per file and referred to by number afterwards.
+pub(crate) fn encode(record: &LogData, interner: Option<&mut Interner>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let source_id = interner.map(|interner| {
+        let next = interner.sources.len() as u64;
+        *interner.sources.entry(record.source.clone()).or_insert_with(|| {
+            let mut body = Vec::new();
+            put_varint(&mut body, next);
+            put_str(&mut body, &record.source);
+            frame(&mut out, FRAME_SOURCE, &body);
+            next
+        })
+    });
+    frame(&mut out, FRAME_RECORD, &encode_body(record, source_id));
+    out
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn corrupt() -> LoggerError {
+        LoggerError::InvalidData("corrupt binary log record".to_string())
+    }
+
+    fn byte(&mut self) -> Result<u8, LoggerError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or_else(Cursor::corrupt)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, LoggerError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Cursor::corrupt())
+    }
+
+    fn bytes(&mut self, len: u64) -> Result<&'a [u8], LoggerError> {
+        if len > self.bytes.len() as u64 {
+            return Err(Cursor::corrupt());
+        }
+        let (bytes, rest) = self.bytes.split_at(len as usize);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, LoggerError> {
+        let len = self.varint()?;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, LoggerError> {
+        Ok(Some(self.string()?).filter(|value| !value.is_empty()))
+    }
+}
+
+/// Reads back what [`encode_body`] wrote, looking interned sources up in `sources`.
+pub(crate) fn decode_body(body: &[u8], sources: &HashMap<u64, String>) -> Result<LogData, LoggerError> {
+    let mut cursor = Cursor { bytes: body };
+    let time = DateTime::from_timestamp_micros(unzigzag(cursor.varint()?)).ok_or_else(Cursor::corrupt)?;
+    let level = Level::from_usize(cursor.byte()? as usize).ok_or_else(Cursor::corrupt)?;
+    let sequence = cursor.varint()?;
+    let thread = cursor.varint()?;
+    let thread_name = cursor.optional_string()?;
+    let source = match cursor.varint()? {
+        INLINE_SOURCE => cursor.string()?,
+        tag => sources.get(&(tag >> 1)).cloned().ok_or_else(Cursor::corrupt)?,
+    };
+    let module_path = cursor.optional_string()?;
+    let file = cursor.optional_string()?;
+    let line = cursor.varint()?.checked_sub(1).map(|line| line as u32);
+    let message = cursor.string()?;
+    let count = cursor.varint()?;
+    let mut key_values = Vec::new();
+    for _ in 0..count {
+        key_values.push((cursor.string()?, cursor.string()?));
+    }
+    Ok(LogData {
+        message,
+        level,
+        source,
+        time,
+        sequence,
+        thread,
+        thread_name,
+        module_path,
+        file,
+        line,
+        key_values,
+    })
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    for (index, shift) in (0..64).step_by(7).enumerate() {
+        let mut byte = [0u8; 1];
+        if let Err(e) = reader.read_exact(&mut byte) {
+            // A clean end of file can only fall before the first byte.
+            return match e.kind() {
+                io::ErrorKind::UnexpectedEof if index == 0 => Ok(None),
+                _ => Err(e),
+            };
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint too long"))
+}
+
+/// Decodes a whole binary log, header included, into its records in file
+/// order. A record cut short at the end, as a crash leaves it, is dropped.
+pub fn decode<R: Read>(mut reader: R) -> Result<Vec<LogData>, LoggerError> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    if !is_binary(&header) {
+        return Err(LoggerError::InvalidData("not a binary log file".to_string()));
+    }
+    if header[MAGIC.len()] > VERSION {
+        return Err(LoggerError::InvalidData(format!(
+            "unsupported binary log version {}",
+            header[MAGIC.len()]
+        )));
+    }
+
+    let mut sources = HashMap::new();
+    let mut records = Vec::new();
+    loop {
+        let len = match read_varint(&mut reader) {
+            Ok(Some(len)) if len > 0 && len <= MAX_FRAME_LEN => len,
+            Ok(None) => break,
+            Ok(Some(_)) => return Err(Cursor::corrupt()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        let mut body = vec![0u8; len as usize];
+        match reader.read_exact(&mut body) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            result => result?,
+        }
+        let mut cursor = Cursor { bytes: &body };
+        match cursor.byte()? {
+            FRAME_SOURCE => {
+                let id = cursor.varint()?;
+                sources.insert(id, cursor.string()?);
+            }
+            FRAME_RECORD => records.push(decode_body(cursor.bytes, &sources)?),
+            _ => {}
+        }
+    }
+    Ok(records)
+}
@@ -0,0 +1,161 @@
+use crate::models::error::LoggerError;
+use crate::models::rotation;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Marks a half-written archive; such files are never read and are cleaned up.
+pub const TEMP_EXTENSION: &str = "tmp";
+
+lazy_static::lazy_static! {
+    static ref COMPRESSOR: Mutex<Option<mpsc::Sender<CompressJob>>> = Mutex::new(None);
+}
+
+/// How closed log files are compressed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Every extension a finished archive can carry, for code that has to find them.
+    pub const EXTENSIONS: [&'static str; 2] = ["gz", "zst"];
+
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    fn from_path(path: &Path) -> Compression {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = LoggerError;
+
+    fn from_str(compression: &str) -> Result<Compression, Self::Err> {
+        match compression.to_ascii_lowercase().as_str() {
+            "none" | "" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown compression `{}`", compression))),
+        }
+    }
+}
+
+/// Where the compressed copy replaces the original once it is complete.
+pub(crate) enum Target {
+    /// A numbered backup of `base`. Backups keep shifting while we compress, so
+    /// the original is looked up again by inode when the archive is done.
+    Backup { base: PathBuf, max_backups: usize },
+    /// A file closed at the end of its period, which keeps its name.
+    Closed(PathBuf),
+}
+
+pub(crate) struct CompressJob {
+    pub(crate) source: File,
+    pub(crate) target: Target,
+    pub(crate) compression: Compression,
+}
+
+/// Hands a closed file to the background compressor.
+pub(crate) fn enqueue(job: CompressJob) -> Result<(), LoggerError> {
+    let mut compressor = COMPRESSOR.lock()?;
+    let sender = match compressor.as_ref() {
+        Some(sender) => sender.clone(),
+        None => {
+            let (sender, receiver) = mpsc::channel::<CompressJob>();
+            thread::Builder::new()
+                .name("common_logger-compress".to_string())
+                .spawn(move || {
+                    for job in receiver {
+                        if let Err(e) = compress(job) {
+                            eprintln!("ERROR::COMPRESSING LOG FILE: {}", e);
+                        }
+                    }
+                })?;
+            compressor.insert(sender).clone()
+        }
+    };
+    sender.send(job).map_err(|_| LoggerError::ThreadPool)
+}
+
+fn compress(job: CompressJob) -> Result<(), LoggerError> {
+    let Some(extension) = job.compression.extension() else {
+        return Ok(());
+    };
+    let inode = job.source.metadata()?.ino();
+    let directory = match &job.target {
+        Target::Backup { base, .. } => base.parent(),
+        Target::Closed(path) => path.parent(),
+    }
+    .map(Path::to_path_buf)
+    .unwrap_or_default();
+    // Processes sharing the directory may compress the same closed file at once.
+    let temp = directory.join(format!(".compress-{}-{}.{}", std::process::id(), inode, TEMP_EXTENSION));
+
+    let written = write_archive(job.source, &temp, job.compression);
+    let finished = written.and_then(|_| match &job.target {
+        Target::Backup { base, max_backups } => rotation::replace_backup(base, *max_backups, inode, &temp, extension),
+        Target::Closed(path) => rotation::replace_closed(path, inode, &temp, extension),
+    });
+    if finished.is_err() || temp.exists() {
+        let _ = fs::remove_file(&temp);
+    }
+    finished
+}
+
+fn write_archive(source: File, temp: &Path, compression: Compression) -> Result<(), LoggerError> {
+    let mut reader = BufReader::new(source);
+    let out = File::create(temp)?;
+    let out = match compression {
+        Compression::None => return Ok(()),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(out, flate2::Compression::default());
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::Encoder::new(out, 0)?;
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+    };
+    // The archive must be on disk before it replaces the original.
+    out.sync_all()?;
+    Ok(())
+}
+
+/// Opens a log file for reading, decompressing `.gz` and `.zst` files on the fly.
+pub fn open_reader(path: &Path) -> Result<Box<dyn Read>, LoggerError> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::stream::Decoder::with_buffer(file)?),
+    })
+}
+
+pub(crate) fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
@@ -0,0 +1,564 @@
+use crate::models::compression::Compression;
+use crate::models::durability::Durability;
+use crate::models::error::LoggerError;
+use crate::models::flight_recorder::{self, FlightRecorder};
+use crate::models::fluentd::FluentdOutput;
+use crate::models::format::LogFormat;
+use crate::models::gelf::GelfOutput;
+use crate::models::http::HttpOutput;
+use crate::models::journald::JournaldOutput;
+use crate::models::loki::LokiOutput;
+use crate::models::naming::FileNamePattern;
+use crate::models::network::NetworkOutput;
+use crate::models::remote::RemoteOutput;
+use crate::models::retention::RetentionPolicy;
+use crate::models::rotation::RotationPolicy;
+use crate::models::routing::Route;
+use crate::models::schedule::{LogTimeZone, Schedule};
+use crate::models::service::ServiceInfo;
+use crate::models::syslog::SyslogOutput;
+use crate::models::template::Template;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<Arc<LoggerConfig>> = RwLock::new(Arc::new(LoggerConfig::default()));
+}
+
+/// Where and how the background workers write their files.
+#[derive(Clone, Debug)]
+pub struct LoggerConfig {
+    pub(crate) directory: PathBuf,
+    pub(crate) file_name_pattern: FileNamePattern,
+    pub(crate) rotation: RotationPolicy,
+    pub(crate) timezone: LogTimeZone,
+    pub(crate) retention: RetentionPolicy,
+    pub(crate) routes: Vec<Route>,
+    pub(crate) durability: Durability,
+    pub(crate) spool_threshold: Option<usize>,
+    pub(crate) flight_recorder: Option<FlightRecorder>,
+    pub(crate) format: LogFormat,
+    pub(crate) console_format: LogFormat,
+    pub(crate) template: Option<Template>,
+    pub(crate) console_template: Option<Template>,
+    pub(crate) service: ServiceInfo,
+    pub(crate) syslog: Option<SyslogOutput>,
+    pub(crate) journald: Option<JournaldOutput>,
+    pub(crate) network: Option<NetworkOutput>,
+    pub(crate) http: Option<HttpOutput>,
+    pub(crate) gelf: Option<GelfOutput>,
+    pub(crate) fluentd: Option<FluentdOutput>,
+    pub(crate) loki: Option<LokiOutput>,
+    pub(crate) remote: Option<RemoteOutput>,
+}
+
+impl LoggerConfig {
+    #[inline]
+    pub fn builder() -> LoggerConfigBuilder {
+        LoggerConfigBuilder::new()
+    }
+
+    #[inline]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    #[inline]
+    pub fn file_name_pattern(&self) -> &FileNamePattern {
+        &self.file_name_pattern
+    }
+
+    #[inline]
+    pub fn rotation(&self) -> &RotationPolicy {
+        &self.rotation
+    }
+
+    #[inline]
+    pub fn timezone(&self) -> LogTimeZone {
+        self.timezone
+    }
+
+    #[inline]
+    pub fn retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    #[inline]
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// The durability of routes that do not set their own.
+    #[inline]
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    #[inline]
+    pub fn spool_threshold(&self) -> Option<usize> {
+        self.spool_threshold
+    }
+
+    #[inline]
+    pub fn flight_recorder(&self) -> Option<&FlightRecorder> {
+        self.flight_recorder.as_ref()
+    }
+
+    #[inline]
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    #[inline]
+    pub fn console_format(&self) -> LogFormat {
+        self.console_format
+    }
+
+    #[inline]
+    pub fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+
+    #[inline]
+    pub fn console_template(&self) -> Option<&Template> {
+        self.console_template.as_ref()
+    }
+
+    #[inline]
+    pub fn service(&self) -> &ServiceInfo {
+        &self.service
+    }
+
+    #[inline]
+    pub fn syslog(&self) -> Option<&SyslogOutput> {
+        self.syslog.as_ref()
+    }
+
+    #[inline]
+    pub fn journald(&self) -> Option<&JournaldOutput> {
+        self.journald.as_ref()
+    }
+
+    #[inline]
+    pub fn network(&self) -> Option<&NetworkOutput> {
+        self.network.as_ref()
+    }
+
+    #[inline]
+    pub fn http(&self) -> Option<&HttpOutput> {
+        self.http.as_ref()
+    }
+
+    #[inline]
+    pub fn gelf(&self) -> Option<&GelfOutput> {
+        self.gelf.as_ref()
+    }
+
+    #[inline]
+    pub fn fluentd(&self) -> Option<&FluentdOutput> {
+        self.fluentd.as_ref()
+    }
+
+    #[inline]
+    pub fn loki(&self) -> Option<&LokiOutput> {
+        self.loki.as_ref()
+    }
+
+    #[inline]
+    pub fn remote(&self) -> Option<&RemoteOutput> {
+        self.remote.as_ref()
+    }
+
+    /// Whether `file_name` in the log directory was written by one of the routes.
+    pub fn is_log_file(&self, file_name: &str) -> bool {
+        self.routes
+            .iter()
+            .any(|route| route.file_name_pattern(&self.file_name_pattern).matches_file(file_name))
+    }
+}
+
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        LoggerConfig {
+            directory: PathBuf::from("."),
+            file_name_pattern: FileNamePattern::default(),
+            rotation: RotationPolicy::default(),
+            timezone: LogTimeZone::default(),
+            retention: RetentionPolicy::default(),
+            routes: vec![Route::default()],
+            durability: Durability::default(),
+            spool_threshold: None,
+            flight_recorder: None,
+            format: LogFormat::default(),
+            console_format: LogFormat::default(),
+            template: None,
+            console_template: None,
+            service: ServiceInfo::default(),
+            syslog: None,
+            journald: None,
+            network: None,
+            http: None,
+            gelf: None,
+            fluentd: None,
+            loki: None,
+            remote: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LoggerConfigBuilder {
+    config: LoggerConfig,
+    file_name_pattern: Option<String>,
+}
+
+impl LoggerConfigBuilder {
+    #[inline]
+    pub fn new() -> LoggerConfigBuilder {
+        LoggerConfigBuilder {
+            config: LoggerConfig::default(),
+            file_name_pattern: None,
+        }
+    }
+
+    /// Starts from an existing configuration, so single settings can be changed.
+    #[inline]
+    pub fn from_config(config: &LoggerConfig) -> LoggerConfigBuilder {
+        LoggerConfigBuilder {
+            config: config.clone(),
+            file_name_pattern: None,
+        }
+    }
+
+    #[inline]
+    pub fn directory<P: Into<PathBuf>>(&mut self, directory: P) -> &mut LoggerConfigBuilder {
+        self.config.directory = directory.into();
+        self
+    }
+
+    #[inline]
+    pub fn file_name_pattern(&mut self, pattern: &str) -> &mut LoggerConfigBuilder {
+        self.file_name_pattern = Some(pattern.to_string());
+        self
+    }
+
+    /// Rotates a file once it would grow past `max_bytes`; `None` never rotates by size.
+    #[inline]
+    pub fn max_file_size(&mut self, max_bytes: Option<u64>) -> &mut LoggerConfigBuilder {
+        self.config.rotation.max_bytes = max_bytes;
+        self
+    }
+
+    #[inline]
+    pub fn max_backups(&mut self, max_backups: usize) -> &mut LoggerConfigBuilder {
+        self.config.rotation.max_backups = max_backups;
+        self
+    }
+
+    /// Also rotates at every boundary of `schedule`; combines with the size limit.
+    #[inline]
+    pub fn rotation_schedule(&mut self, schedule: Option<Schedule>) -> &mut LoggerConfigBuilder {
+        self.config.rotation.schedule = schedule;
+        self
+    }
+
+    /// Compresses rotated and closed files on a background thread.
+    #[inline]
+    pub fn compression(&mut self, compression: Compression) -> &mut LoggerConfigBuilder {
+        self.config.rotation.compression = compression;
+        self
+    }
+
+    /// Lets several processes append to and rotate the same log files.
+    #[inline]
+    pub fn multi_process(&mut self, multi_process: bool) -> &mut LoggerConfigBuilder {
+        self.config.rotation.multi_process = multi_process;
+        self
+    }
+
+    /// Time zone for file names, timestamps and schedule boundaries. Defaults to UTC.
+    #[inline]
+    pub fn timezone(&mut self, timezone: LogTimeZone) -> &mut LoggerConfigBuilder {
+        self.config.timezone = timezone;
+        self
+    }
+
+    /// Deletes log files last written longer ago than `max_age`.
+    #[inline]
+    pub fn max_age(&mut self, max_age: Option<Duration>) -> &mut LoggerConfigBuilder {
+        self.config.retention.max_age = max_age;
+        self
+    }
+
+    /// Keeps at most `max_files` log files, backups included.
+    #[inline]
+    pub fn max_files(&mut self, max_files: Option<usize>) -> &mut LoggerConfigBuilder {
+        self.config.retention.max_files = max_files;
+        self
+    }
+
+    /// Keeps all log files together under `max_bytes`.
+    #[inline]
+    pub fn max_total_size(&mut self, max_bytes: Option<u64>) -> &mut LoggerConfigBuilder {
+        self.config.retention.max_total_bytes = max_bytes;
+        self
+    }
+
+    /// Replaces every route. Without routes no files are written at all.
+    #[inline]
+    pub fn routes(&mut self, routes: Vec<Route>) -> &mut LoggerConfigBuilder {
+        self.config.routes = routes;
+        self
+    }
+
+    /// Gives every route the same durability, those added later included;
+    /// routes then set their own with [`Route::durability`].
+    #[inline]
+    pub fn durability(&mut self, durability: Durability) -> &mut LoggerConfigBuilder {
+        self.config.durability = durability;
+        for route in &mut self.config.routes {
+            route.durability = None;
+        }
+        self
+    }
+
+    /// Adds a route; records it accepts are written to its files as well.
+    #[inline]
+    pub fn route(&mut self, route: Route) -> &mut LoggerConfigBuilder {
+        if !self.config.routes.contains(&route) {
+            self.config.routes.push(route);
+        }
+        self
+    }
+
+    /// Once `threshold` records are waiting to be written, further ones go to
+    /// a spool file in the log directory and are replayed in order as the
+    /// queue drains. `None` keeps everything in memory.
+    #[inline]
+    pub fn spool(&mut self, threshold: Option<usize>) -> &mut LoggerConfigBuilder {
+        self.config.spool_threshold = threshold;
+        self
+    }
+
+    /// Also keeps the latest records in a memory-mapped ring file, whatever
+    /// the log level and routes say.
+    #[inline]
+    pub fn flight_recorder(&mut self, recorder: Option<FlightRecorder>) -> &mut LoggerConfigBuilder {
+        self.config.flight_recorder = recorder;
+        self
+    }
+
+    /// How records are laid out in log files. Files already holding another
+    /// format should be rotated away first; the formats do not mix.
+    #[inline]
+    pub fn format(&mut self, format: LogFormat) -> &mut LoggerConfigBuilder {
+        self.config.format = format;
+        self
+    }
+
+    /// How records are printed to the console.
+    #[inline]
+    pub fn console_format(&mut self, format: LogFormat) -> &mut LoggerConfigBuilder {
+        self.config.console_format = format;
+        self
+    }
+
+    /// Lays out `Text` file lines with `template` instead of the built-in layout.
+    #[inline]
+    pub fn template(&mut self, template: Option<Template>) -> &mut LoggerConfigBuilder {
+        self.config.template = template;
+        self
+    }
+
+    /// Lays out `Text` console lines with `template` instead of the built-in,
+    /// colored layout.
+    #[inline]
+    pub fn console_template(&mut self, template: Option<Template>) -> &mut LoggerConfigBuilder {
+        self.config.console_template = template;
+        self
+    }
+
+    /// The `service.name` of the ECS layout; the executable's name by default.
+    #[inline]
+    pub fn service_name(&mut self, name: &str) -> &mut LoggerConfigBuilder {
+        self.config.service.name = name.to_string();
+        self
+    }
+
+    /// The `service.version` of the ECS layout; left out by default.
+    #[inline]
+    pub fn service_version(&mut self, version: Option<&str>) -> &mut LoggerConfigBuilder {
+        self.config.service.version = version.map(str::to_string);
+        self
+    }
+
+    /// The `host.name` of the ECS layout; the machine's hostname by default.
+    #[inline]
+    pub fn host_name(&mut self, host: &str) -> &mut LoggerConfigBuilder {
+        self.config.service.host = host.to_string();
+        self
+    }
+
+    /// Also sends records to a syslog daemon.
+    #[inline]
+    pub fn syslog(&mut self, output: Option<SyslogOutput>) -> &mut LoggerConfigBuilder {
+        self.config.syslog = output;
+        self
+    }
+
+    /// Also sends records to the systemd journal.
+    #[inline]
+    pub fn journald(&mut self, output: Option<JournaldOutput>) -> &mut LoggerConfigBuilder {
+        self.config.journald = output;
+        self
+    }
+
+    /// Also streams records to a TCP or Unix socket.
+    #[inline]
+    pub fn network(&mut self, output: Option<NetworkOutput>) -> &mut LoggerConfigBuilder {
+        self.config.network = output;
+        self
+    }
+
+    /// Also POSTs records in batches to an HTTP endpoint.
+    #[inline]
+    pub fn http(&mut self, output: Option<HttpOutput>) -> &mut LoggerConfigBuilder {
+        self.config.http = output;
+        self
+    }
+
+    /// Also sends records to Graylog as GELF.
+    #[inline]
+    pub fn gelf(&mut self, output: Option<GelfOutput>) -> &mut LoggerConfigBuilder {
+        self.config.gelf = output;
+        self
+    }
+
+    /// Also forwards records to a Fluentd or Fluent Bit agent.
+    #[inline]
+    pub fn fluentd(&mut self, output: Option<FluentdOutput>) -> &mut LoggerConfigBuilder {
+        self.config.fluentd = output;
+        self
+    }
+
+    /// Also pushes records to Grafana Loki.
+    #[inline]
+    pub fn loki(&mut self, output: Option<LokiOutput>) -> &mut LoggerConfigBuilder {
+        self.config.loki = output;
+        self
+    }
+
+    /// Also hands records to a `ul-collectord` daemon on this machine.
+    #[inline]
+    pub fn remote(&mut self, output: Option<RemoteOutput>) -> &mut LoggerConfigBuilder {
+        self.config.remote = output;
+        self
+    }
+
+    pub fn build(&self) -> Result<LoggerConfig, LoggerError> {
+        let mut config = self.config.clone();
+        if config.directory.as_os_str().is_empty() {
+            return Err(LoggerError::InvalidConfig("log directory is empty".to_string()));
+        }
+        if config.rotation.max_bytes == Some(0) {
+            return Err(LoggerError::InvalidConfig("rotation size must be greater than zero".to_string()));
+        }
+        if config.spool_threshold == Some(0) {
+            return Err(LoggerError::InvalidConfig("spool threshold must be greater than zero".to_string()));
+        }
+        if !config.console_format.is_textual() {
+            return Err(LoggerError::InvalidConfig(format!(
+                "{:?} cannot be printed to the console",
+                config.console_format
+            )));
+        }
+        if let Some(recorder) = &config.flight_recorder {
+            if recorder.capacity < flight_recorder::MIN_CAPACITY || recorder.capacity > isize::MAX as u64 {
+                return Err(LoggerError::InvalidConfig(format!(
+                    "flight recorder capacity must be at least {} bytes",
+                    flight_recorder::MIN_CAPACITY
+                )));
+            }
+        }
+        if config.service.name.is_empty() || config.service.host.is_empty() {
+            return Err(LoggerError::InvalidConfig("service and host names must not be empty".to_string()));
+        }
+        if config.syslog.as_ref().is_some_and(|syslog| syslog.app_name.is_empty()) {
+            return Err(LoggerError::InvalidConfig("syslog app name is empty".to_string()));
+        }
+        if config.journald.as_ref().is_some_and(|journald| journald.identifier.is_empty()) {
+            return Err(LoggerError::InvalidConfig("journald identifier is empty".to_string()));
+        }
+        if config.network.as_ref().is_some_and(|network| network.queue_capacity == 0) {
+            return Err(LoggerError::InvalidConfig("network queue capacity must be greater than zero".to_string()));
+        }
+        if let Some(http) = &config.http {
+            http.validate()?;
+        }
+        if let Some(gelf) = &config.gelf {
+            gelf.validate()?;
+        }
+        if let Some(fluentd) = &config.fluentd {
+            fluentd.validate()?;
+        }
+        if let Some(loki) = &config.loki {
+            loki.validate()?;
+        }
+        if let Some(remote) = &config.remote {
+            if remote.client_name.is_empty() {
+                return Err(LoggerError::InvalidConfig("remote client name is empty".to_string()));
+            }
+            if remote.stream.queue_capacity == 0 {
+                return Err(LoggerError::InvalidConfig("remote queue capacity must be greater than zero".to_string()));
+            }
+        }
+        if let Some(pattern) = &self.file_name_pattern {
+            config.file_name_pattern = pattern.parse()?;
+        }
+        if let Some(schedule) = config.rotation.schedule {
+            let patterns = config.routes.iter().map(|route| route.file_name_pattern(&config.file_name_pattern));
+            if let Some(pattern) = patterns.into_iter().find(|pattern| pattern.changes_within(schedule)) {
+                return Err(LoggerError::InvalidConfig(format!(
+                    "file name pattern `{}` starts new files within a {:?} rotation period",
+                    pattern, schedule
+                )));
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Default for LoggerConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Installs `config` for every record written from now on. The log directory
+/// is created if it does not exist yet.
+pub fn set_config(config: LoggerConfig) -> Result<(), LoggerError> {
+    fs::create_dir_all(&config.directory)?;
+    *CONFIG.write()? = Arc::new(config);
+    Ok(())
+}
+
+/// Applies `change` to a copy of the current configuration and installs it.
+pub fn update_config<F>(change: F) -> Result<(), LoggerError>
+where
+    F: FnOnce(&mut LoggerConfigBuilder) -> &mut LoggerConfigBuilder,
+{
+    let mut current = CONFIG.write()?;
+    let mut builder = LoggerConfigBuilder::from_config(&current);
+    change(&mut builder);
+    let config = builder.build()?;
+    fs::create_dir_all(&config.directory)?;
+    *current = Arc::new(config);
+    Ok(())
+}
+
+/// A snapshot of the configuration currently in use.
+pub fn config() -> Arc<LoggerConfig> {
+    Arc::clone(&CONFIG.read().unwrap_or_else(PoisonError::into_inner))
+}
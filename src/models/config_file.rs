@@ -0,0 +1,456 @@
+use crate::models::config::{LoggerConfig, LoggerConfigBuilder};
+use crate::models::durability::{Durability, DurabilityMode};
+use crate::models::error::LoggerError;
+use crate::models::flight_recorder::FlightRecorder;
+use crate::models::fluentd::FluentdOutput;
+use crate::models::gelf::{GelfOutput, GelfTransport, GelfTransportKind};
+use crate::models::http::HttpOutput;
+use crate::models::journald::JournaldOutput;
+use crate::models::level::LevelFilter;
+use crate::models::loki::LokiOutput;
+use crate::models::network::{NetworkOutput, NetworkTarget, NetworkTargetKind};
+use crate::models::remote::RemoteOutput;
+use crate::models::routing::Route;
+use crate::models::syslog::{SyslogOutput, SyslogTransport, SyslogTransportKind};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A configuration file: one JSON object whose keys are named after the
+/// [`LoggerConfigBuilder`] methods. Every key is optional and unknown keys
+/// are refused, so a typo never goes unnoticed.
+///
+/// ```json
+/// {
+///     "directory": "logs",
+///     "format": "text",
+///     "template": "{time:%H:%M:%S%.3f} {level:>5} [{source:<20.20}] {msg} {kv}",
+///     "max_file_size": 10485760,
+///     "rotation_schedule": "daily",
+///     "routes": [{ "kind": "per-level", "max_level": "debug", "durability": "sync-prod" }]
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    directory: Option<PathBuf>,
+    file_name_pattern: Option<String>,
+    format: Option<String>,
+    console_format: Option<String>,
+    template: Option<String>,
+    console_template: Option<String>,
+    service_name: Option<String>,
+    service_version: Option<String>,
+    host_name: Option<String>,
+    timezone: Option<String>,
+    max_file_size: Option<u64>,
+    max_backups: Option<usize>,
+    rotation_schedule: Option<String>,
+    compression: Option<String>,
+    multi_process: Option<bool>,
+    max_age_secs: Option<u64>,
+    max_files: Option<usize>,
+    max_total_size: Option<u64>,
+    routes: Option<Vec<RouteEntry>>,
+    spool_threshold: Option<usize>,
+    flight_recorder: Option<FlightRecorderEntry>,
+    syslog: Option<SyslogEntry>,
+    journald: Option<JournaldEntry>,
+    network: Option<NetworkEntry>,
+    http: Option<HttpEntry>,
+    gelf: Option<GelfEntry>,
+    fluentd: Option<FluentdEntry>,
+    loki: Option<LokiEntry>,
+    remote: Option<RemoteEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteEntry {
+    kind: String,
+    pattern: Option<String>,
+    max_level: Option<String>,
+    durability: Option<String>,
+    sync_interval_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlightRecorderEntry {
+    path: PathBuf,
+    capacity: u64,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SyslogEntry {
+    transport: String,
+    address: Option<String>,
+    facility: Option<String>,
+    app_name: Option<String>,
+    protocol: Option<String>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JournaldEntry {
+    socket: Option<PathBuf>,
+    identifier: Option<String>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NetworkEntry {
+    target: String,
+    address: String,
+    framing: Option<String>,
+    queue_capacity: Option<usize>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpEntry {
+    url: String,
+    encoding: Option<String>,
+    gzip: Option<bool>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    max_records: Option<usize>,
+    max_bytes: Option<usize>,
+    max_delay_ms: Option<u64>,
+    max_retries: Option<u32>,
+    timeout_ms: Option<u64>,
+    queue_capacity: Option<usize>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GelfEntry {
+    transport: String,
+    address: String,
+    compress: Option<bool>,
+    chunk_size: Option<usize>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FluentdEntry {
+    address: String,
+    tag_prefix: Option<String>,
+    require_ack: Option<bool>,
+    max_records: Option<usize>,
+    max_delay_ms: Option<u64>,
+    ack_timeout_ms: Option<u64>,
+    queue_capacity: Option<usize>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LokiEntry {
+    url: String,
+    label_fields: Option<Vec<String>>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    tenant: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    gzip: Option<bool>,
+    max_records: Option<usize>,
+    max_bytes: Option<usize>,
+    max_delay_ms: Option<u64>,
+    queue_capacity: Option<usize>,
+    max_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoteEntry {
+    socket: PathBuf,
+    client_name: Option<String>,
+    queue_capacity: Option<usize>,
+    max_level: Option<String>,
+}
+
+fn parse<T: FromStr<Err = LoggerError>>(value: Option<&String>) -> Result<Option<T>, LoggerError> {
+    value.map(|value| value.parse()).transpose()
+}
+
+fn level_filter(level: Option<&String>) -> Result<LevelFilter, LoggerError> {
+    match level {
+        Some(level) => level
+            .parse()
+            .map_err(|_| LoggerError::InvalidConfig(format!("unknown level `{}`", level))),
+        None => Ok(LevelFilter::Trace),
+    }
+}
+
+impl RouteEntry {
+    fn into_route(self) -> Result<Route, LoggerError> {
+        let mut route = Route::new(self.kind.parse()?).max_level(level_filter(self.max_level.as_ref())?);
+        if let Some(pattern) = &self.pattern {
+            route = route.pattern(pattern)?;
+        }
+        if let Some(mode) = parse::<DurabilityMode>(self.durability.as_ref())? {
+            let interval = Duration::from_millis(self.sync_interval_ms.unwrap_or_default());
+            route = route.durability(Durability::from_mode(mode, interval)?);
+        }
+        Ok(route)
+    }
+}
+
+impl SyslogEntry {
+    fn into_output(self) -> Result<SyslogOutput, LoggerError> {
+        let kind: SyslogTransportKind = self.transport.parse()?;
+        let transport = SyslogTransport::new(kind, self.address.as_deref().unwrap_or_default())?;
+        let mut output = SyslogOutput::new(transport).max_level(level_filter(self.max_level.as_ref())?);
+        if let Some(facility) = parse(self.facility.as_ref())? {
+            output = output.facility(facility);
+        }
+        if let Some(app_name) = &self.app_name {
+            output = output.app_name(app_name);
+        }
+        if let Some(protocol) = parse(self.protocol.as_ref())? {
+            output = output.protocol(protocol);
+        }
+        Ok(output)
+    }
+}
+
+impl HttpEntry {
+    fn into_output(self) -> Result<HttpOutput, LoggerError> {
+        let mut output = HttpOutput::new(&self.url).max_level(level_filter(self.max_level.as_ref())?);
+        if let Some(encoding) = parse(self.encoding.as_ref())? {
+            output = output.encoding(encoding);
+        }
+        if let Some(gzip) = self.gzip {
+            output = output.gzip(gzip);
+        }
+        for (name, value) in &self.headers {
+            output = output.header(name, value);
+        }
+        if let Some(max_records) = self.max_records {
+            output = output.max_records(max_records);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            output = output.max_bytes(max_bytes);
+        }
+        if let Some(delay) = self.max_delay_ms {
+            output = output.max_delay(Duration::from_millis(delay));
+        }
+        if let Some(max_retries) = self.max_retries {
+            output = output.max_retries(max_retries);
+        }
+        if let Some(timeout) = self.timeout_ms {
+            output = output.timeout(Duration::from_millis(timeout));
+        }
+        if let Some(capacity) = self.queue_capacity {
+            output = output.queue_capacity(capacity);
+        }
+        Ok(output)
+    }
+}
+
+impl FluentdEntry {
+    fn into_output(self) -> Result<FluentdOutput, LoggerError> {
+        let mut output = FluentdOutput::new(&self.address)
+            .require_ack(self.require_ack.unwrap_or_default())
+            .max_level(level_filter(self.max_level.as_ref())?);
+        if let Some(prefix) = &self.tag_prefix {
+            output = output.tag_prefix(prefix);
+        }
+        if let Some(max_records) = self.max_records {
+            output = output.max_records(max_records);
+        }
+        if let Some(delay) = self.max_delay_ms {
+            output = output.max_delay(Duration::from_millis(delay));
+        }
+        if let Some(timeout) = self.ack_timeout_ms {
+            output = output.ack_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(capacity) = self.queue_capacity {
+            output = output.queue_capacity(capacity);
+        }
+        Ok(output)
+    }
+}
+
+impl LokiEntry {
+    fn into_output(self) -> Result<LokiOutput, LoggerError> {
+        let mut output = LokiOutput::new(&self.url)
+            .gzip(self.gzip.unwrap_or_default())
+            .max_level(level_filter(self.max_level.as_ref())?);
+        if let Some(fields) = &self.label_fields {
+            let fields = fields.iter().map(|field| field.parse()).collect::<Result<Vec<_>, _>>()?;
+            output = output.label_fields(&fields);
+        }
+        for (name, value) in &self.labels {
+            output = output.label(name, value);
+        }
+        if let Some(tenant) = &self.tenant {
+            output = output.tenant(tenant);
+        }
+        for (name, value) in &self.headers {
+            output = output.header(name, value);
+        }
+        if let Some(max_records) = self.max_records {
+            output = output.max_records(max_records);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            output = output.max_bytes(max_bytes);
+        }
+        if let Some(delay) = self.max_delay_ms {
+            output = output.max_delay(Duration::from_millis(delay));
+        }
+        if let Some(capacity) = self.queue_capacity {
+            output = output.queue_capacity(capacity);
+        }
+        Ok(output)
+    }
+}
+
+impl ConfigFile {
+    fn apply(self, builder: &mut LoggerConfigBuilder) -> Result<(), LoggerError> {
+        if let Some(directory) = self.directory {
+            builder.directory(directory);
+        }
+        if let Some(pattern) = &self.file_name_pattern {
+            builder.file_name_pattern(pattern);
+        }
+        if let Some(format) = parse(self.format.as_ref())? {
+            builder.format(format);
+        }
+        if let Some(format) = parse(self.console_format.as_ref())? {
+            builder.console_format(format);
+        }
+        if let Some(template) = parse(self.template.as_ref())? {
+            builder.template(Some(template));
+        }
+        if let Some(template) = parse(self.console_template.as_ref())? {
+            builder.console_template(Some(template));
+        }
+        if let Some(name) = &self.service_name {
+            builder.service_name(name);
+        }
+        if let Some(version) = &self.service_version {
+            builder.service_version(Some(version));
+        }
+        if let Some(host) = &self.host_name {
+            builder.host_name(host);
+        }
+        if let Some(timezone) = parse(self.timezone.as_ref())? {
+            builder.timezone(timezone);
+        }
+        if self.max_file_size.is_some() {
+            builder.max_file_size(self.max_file_size);
+        }
+        if let Some(max_backups) = self.max_backups {
+            builder.max_backups(max_backups);
+        }
+        if let Some(schedule) = parse(self.rotation_schedule.as_ref())? {
+            builder.rotation_schedule(Some(schedule));
+        }
+        if let Some(compression) = parse(self.compression.as_ref())? {
+            builder.compression(compression);
+        }
+        if let Some(multi_process) = self.multi_process {
+            builder.multi_process(multi_process);
+        }
+        if let Some(seconds) = self.max_age_secs {
+            builder.max_age(Some(Duration::from_secs(seconds)));
+        }
+        if self.max_files.is_some() {
+            builder.max_files(self.max_files);
+        }
+        if self.max_total_size.is_some() {
+            builder.max_total_size(self.max_total_size);
+        }
+        if let Some(routes) = self.routes {
+            let routes = routes.into_iter().map(RouteEntry::into_route).collect::<Result<_, _>>()?;
+            builder.routes(routes);
+        }
+        if self.spool_threshold.is_some() {
+            builder.spool(self.spool_threshold);
+        }
+        if let Some(recorder) = self.flight_recorder {
+            let max_level = level_filter(recorder.max_level.as_ref())?;
+            builder.flight_recorder(Some(FlightRecorder::new(recorder.path, recorder.capacity).max_level(max_level)));
+        }
+        if let Some(syslog) = self.syslog {
+            builder.syslog(Some(syslog.into_output()?));
+        }
+        if let Some(journald) = self.journald {
+            let mut output = JournaldOutput::new().max_level(level_filter(journald.max_level.as_ref())?);
+            if let Some(socket) = journald.socket {
+                output = output.socket(socket);
+            }
+            if let Some(identifier) = &journald.identifier {
+                output = output.identifier(identifier);
+            }
+            builder.journald(Some(output));
+        }
+        if let Some(network) = self.network {
+            let kind: NetworkTargetKind = network.target.parse()?;
+            let mut output = NetworkOutput::new(NetworkTarget::new(kind, &network.address)?)
+                .max_level(level_filter(network.max_level.as_ref())?);
+            if let Some(framing) = parse(network.framing.as_ref())? {
+                output = output.framing(framing);
+            }
+            if let Some(capacity) = network.queue_capacity {
+                output = output.queue_capacity(capacity);
+            }
+            builder.network(Some(output));
+        }
+        if let Some(http) = self.http {
+            builder.http(Some(http.into_output()?));
+        }
+        if let Some(gelf) = self.gelf {
+            let kind: GelfTransportKind = gelf.transport.parse()?;
+            let mut output = GelfOutput::new(GelfTransport::new(kind, &gelf.address)?)
+                .compress(gelf.compress.unwrap_or_default())
+                .max_level(level_filter(gelf.max_level.as_ref())?);
+            if let Some(chunk_size) = gelf.chunk_size {
+                output = output.chunk_size(chunk_size);
+            }
+            builder.gelf(Some(output));
+        }
+        if let Some(fluentd) = self.fluentd {
+            builder.fluentd(Some(fluentd.into_output()?));
+        }
+        if let Some(loki) = self.loki {
+            builder.loki(Some(loki.into_output()?));
+        }
+        if let Some(remote) = self.remote {
+            let mut output = RemoteOutput::new(remote.socket).max_level(level_filter(remote.max_level.as_ref())?);
+            if let Some(name) = &remote.client_name {
+                output = output.client_name(name);
+            }
+            if let Some(capacity) = remote.queue_capacity {
+                output = output.queue_capacity(capacity);
+            }
+            builder.remote(Some(output));
+        }
+        Ok(())
+    }
+}
+
+impl LoggerConfig {
+    /// Reads a configuration file, see [`ConfigFile`]; settings it leaves out keep their defaults.
+    pub fn from_file(path: &Path) -> Result<LoggerConfig, LoggerError> {
+        let text = fs::read_to_string(path)?;
+        let file: ConfigFile = serde_json::from_str(&text)
+            .map_err(|e| LoggerError::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+        let mut builder = LoggerConfig::builder();
+        file.apply(&mut builder)?;
+        builder.build()
+    }
+}
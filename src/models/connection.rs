@@ -0,0 +1,103 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// How long a lost connection is left alone before it is tried again; records
+/// sent in between are dropped.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Records are sent from the logging workers, so a peer that stops reading
+/// must not hold them up for longer than this.
+pub(crate) const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) fn resolve(address: &str) -> io::Result<SocketAddr> {
+    address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} did not resolve", address)))
+}
+
+/// A UDP socket connected to `address`, bound to an ephemeral port of the same family.
+pub(crate) fn udp(address: &str) -> io::Result<UdpSocket> {
+    let target = resolve(address)?;
+    let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(target)?;
+    Ok(socket)
+}
+
+/// A TCP stream to `address` whose writes give up after [`WRITE_TIMEOUT`].
+pub(crate) fn tcp(address: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&resolve(address)?, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Where a [`Connection`] sends to, and how it gets a socket there.
+pub(crate) trait Transport: Clone + PartialEq {
+    type Socket;
+
+    fn connect(&self) -> io::Result<Self::Socket>;
+}
+
+/// The one socket an output without a sender thread of its own writes to
+/// from the logging workers. Nothing is queued: while the peer cannot be
+/// reached, records are dropped, and reconnecting waits for `RETRY_DELAY`.
+pub(crate) struct Connection<T: Transport> {
+    transport: Option<T>,
+    socket: Option<T::Socket>,
+    retry_at: Option<Instant>,
+}
+
+impl<T: Transport> Default for Connection<T> {
+    fn default() -> Self {
+        Connection {
+            transport: None,
+            socket: None,
+            retry_at: None,
+        }
+    }
+}
+
+impl<T: Transport> Connection<T> {
+    /// Hands the socket for `transport` to `write`, connecting first if there
+    /// is none or the transport changed. `InvalidInput` errors are the
+    /// message's own and leave the connection as it is.
+    pub(crate) fn send(
+        &mut self,
+        transport: &T,
+        mut write: impl FnMut(&mut T::Socket) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if self.transport.as_ref() != Some(transport) {
+            *self = Connection {
+                transport: Some(transport.clone()),
+                ..Connection::default()
+            };
+        }
+        // A connection that broke since the last record gets one fresh attempt.
+        for _ in 0..2 {
+            if self.socket.is_none() {
+                if self.retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+                    return Ok(());
+                }
+                match transport.connect() {
+                    Ok(socket) => self.socket = Some(socket),
+                    Err(e) => {
+                        self.retry_at = Some(Instant::now() + RETRY_DELAY);
+                        return Err(e);
+                    }
+                }
+            }
+            if let Some(socket) = self.socket.as_mut() {
+                match write(socket) {
+                    Ok(()) => return Ok(()),
+                    Err(e) if e.kind() == io::ErrorKind::InvalidInput => return Err(e),
+                    Err(_) => self.socket = None,
+                }
+            }
+        }
+        self.retry_at = Some(Instant::now() + RETRY_DELAY);
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "connection lost"))
+    }
+}
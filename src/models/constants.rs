@@ -11,6 +11,7 @@ pub const DATE_FORMAT: &str =                "%Y-%m-%d";
 pub const TIME_FORMAT: &str =                "%Y-%m-%d %H:%M:%S";
 pub const LOGGING_THREAD_TIMEOUT: u64 =      2; // in seconds; don't mess with it unless you know what you're doing
 pub const NUM_LOGGING_THREADS: usize =       4;
+pub const LOGGING_BATCH_SIZE: usize =        256; // records a worker writes before flushing its files
 
 pub const NO_INIT_STATE: usize =             0;
 pub const DO_INIT_STATE: usize =             1;
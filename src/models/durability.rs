@@ -0,0 +1,61 @@
+use crate::models::error::LoggerError;
+use std::time::Duration;
+
+/// How hard a file output works to get records onto stable storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Durability {
+    /// Records are buffered and reach the page cache when the buffer fills or
+    /// the background tick flushes it.
+    None,
+    /// The buffer is flushed to the page cache after every batch a worker writes.
+    #[default]
+    FlushEveryBatch,
+    /// Like `FlushEveryBatch`, plus an `fdatasync` at most this often.
+    SyncEvery(Duration),
+    /// Like `FlushEveryBatch`, plus an `fdatasync` after every `Prod` record
+    /// before the worker takes the next record.
+    SyncProd,
+}
+
+/// The [`Durability`] variants as seen from C; the interval travels separately.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DurabilityMode {
+    None,
+    FlushEveryBatch,
+    SyncInterval,
+    SyncProd,
+}
+
+impl std::str::FromStr for DurabilityMode {
+    type Err = LoggerError;
+
+    fn from_str(mode: &str) -> Result<DurabilityMode, Self::Err> {
+        match mode.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "none" => Ok(DurabilityMode::None),
+            "flusheverybatch" | "batch" => Ok(DurabilityMode::FlushEveryBatch),
+            "syncinterval" => Ok(DurabilityMode::SyncInterval),
+            "syncprod" => Ok(DurabilityMode::SyncProd),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown durability `{}`", mode))),
+        }
+    }
+}
+
+impl Durability {
+    pub fn from_mode(mode: DurabilityMode, interval: Duration) -> Result<Durability, LoggerError> {
+        Ok(match mode {
+            DurabilityMode::None => Durability::None,
+            DurabilityMode::FlushEveryBatch => Durability::FlushEveryBatch,
+            DurabilityMode::SyncInterval if interval.is_zero() => {
+                return Err(LoggerError::InvalidConfig("sync interval must be greater than zero".to_string()))
+            }
+            DurabilityMode::SyncInterval => Durability::SyncEvery(interval),
+            DurabilityMode::SyncProd => Durability::SyncProd,
+        })
+    }
+
+    /// Whether the buffer is flushed at the end of each batch.
+    pub fn flushes_batches(&self) -> bool {
+        !matches!(self, Durability::None)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::models::level::Level;
+use crate::models::logger::LogData;
+use crate::models::service::ServiceInfo;
+use chrono::SecondsFormat;
+use serde::{Serialize, Serializer};
+
+/// The ECS version the layout follows, written as `ecs.version`.
+pub const ECS_VERSION: &str = "8.11.0";
+
+/// The ECS layout, in the dotted-key form of the ECS logging libraries.
+/// Fields without a value are left out rather than written as `null`.
+#[derive(Serialize)]
+struct EcsRecord<'a> {
+    #[serde(rename = "@timestamp")]
+    timestamp: String,
+    #[serde(rename = "log.level")]
+    level: &'static str,
+    message: &'a str,
+    #[serde(rename = "ecs.version")]
+    ecs_version: &'static str,
+    #[serde(rename = "log.logger")]
+    logger: &'a str,
+    #[serde(rename = "log.origin.file.name", skip_serializing_if = "Option::is_none")]
+    file: Option<&'a str>,
+    #[serde(rename = "log.origin.file.line", skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    #[serde(rename = "process.pid")]
+    pid: u32,
+    #[serde(rename = "process.thread.id")]
+    thread_id: u64,
+    #[serde(rename = "process.thread.name", skip_serializing_if = "Option::is_none")]
+    thread_name: Option<&'a str>,
+    #[serde(rename = "event.sequence")]
+    sequence: u64,
+    #[serde(rename = "service.name")]
+    service_name: &'a str,
+    #[serde(rename = "service.version", skip_serializing_if = "Option::is_none")]
+    service_version: Option<&'a str>,
+    #[serde(rename = "host.name")]
+    host_name: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty", serialize_with = "as_labels")]
+    labels: &'a [(String, String)],
+}
+
+/// `log.level` as ECS consumers know it, `Prod` being `info`.
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Prod => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+/// ECS label names may not hold dots; they become `_`.
+fn as_labels<S: Serializer>(pairs: &&[(String, String)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(pairs.iter().map(|(key, value)| (key.replace('.', "_"), value)))
+}
+
+/// `record` as one line of ECS JSON, without the newline, for Elasticsearch
+/// pipelines that expect ECS field names. Key-value pairs go under `labels`;
+/// `service.*` and `host.name` come from `service`.
+pub fn line(record: &LogData, service: &ServiceInfo) -> String {
+    let ecs = EcsRecord {
+        timestamp: record.time.to_rfc3339_opts(SecondsFormat::Micros, true),
+        level: level_name(record.level),
+        message: &record.message,
+        ecs_version: ECS_VERSION,
+        logger: &record.source,
+        file: record.file.as_deref(),
+        line: record.line,
+        pid: std::process::id(),
+        thread_id: record.thread,
+        thread_name: record.thread_name.as_deref(),
+        sequence: record.sequence,
+        service_name: &service.name,
+        service_version: service.version.as_deref(),
+        host_name: &service.host,
+        labels: &record.key_values,
+    };
+    // Strings and numbers always serialize; there is no error to report.
+    serde_json::to_string(&ecs).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn levels_are_those_ecs_consumers_know() {
+        let mut record = LogData::test(Level::Prod, "app", "hi").key_values(&[("user.id", "7")]);
+        let service = ServiceInfo::default();
+        for (level, name) in [(Level::Prod, "info"), (Level::Debug, "debug"), (Level::Trace, "trace")] {
+            record.level = level;
+            let line: Value = serde_json::from_str(&line(&record, &service)).unwrap();
+            assert_eq!(line["log.level"], name);
+            assert_eq!(line["labels"]["user_id"], "7");
+            assert!(line.get("service.version").is_none());
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::models::utilities::SetLoggerError;
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::panic::{self, UnwindSafe};
+
+/// Every failure the logger can report, on the Rust side and across FFI.
+#[derive(Debug)]
+pub enum LoggerError {
+    NullPointer,
+    InvalidUtf8,
+    InteriorNul,
+    Io(io::Error),
+    ThreadPool,
+    ThreadId,
+    LockPoisoned,
+    InvalidLevel,
+    AlreadyInitialized,
+    Unsupported(&'static str),
+    InvalidConfig(String),
+    InvalidData(String),
+    Panic(String),
+}
+
+/// Stable error codes returned by every exported function. Values never change
+/// once released; new variants are only ever appended.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoggerErrorCode {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidUtf8 = 2,
+    InteriorNul = 3,
+    Io = 4,
+    ThreadPool = 5,
+    ThreadId = 6,
+    LockPoisoned = 7,
+    InvalidLevel = 8,
+    AlreadyInitialized = 9,
+    Unsupported = 10,
+    InvalidConfig = 11,
+    InvalidData = 12,
+    Panic = 255,
+}
+
+impl LoggerError {
+    pub fn code(&self) -> LoggerErrorCode {
+        match self {
+            LoggerError::NullPointer => LoggerErrorCode::NullPointer,
+            LoggerError::InvalidUtf8 => LoggerErrorCode::InvalidUtf8,
+            LoggerError::InteriorNul => LoggerErrorCode::InteriorNul,
+            LoggerError::Io(_) => LoggerErrorCode::Io,
+            LoggerError::ThreadPool => LoggerErrorCode::ThreadPool,
+            LoggerError::ThreadId => LoggerErrorCode::ThreadId,
+            LoggerError::LockPoisoned => LoggerErrorCode::LockPoisoned,
+            LoggerError::InvalidLevel => LoggerErrorCode::InvalidLevel,
+            LoggerError::AlreadyInitialized => LoggerErrorCode::AlreadyInitialized,
+            LoggerError::Unsupported(_) => LoggerErrorCode::Unsupported,
+            LoggerError::InvalidConfig(_) => LoggerErrorCode::InvalidConfig,
+            LoggerError::InvalidData(_) => LoggerErrorCode::InvalidData,
+            LoggerError::Panic(_) => LoggerErrorCode::Panic,
+        }
+    }
+
+    pub(crate) fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        LoggerError::Panic(message)
+    }
+}
+
+impl fmt::Display for LoggerError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoggerError::NullPointer => fmt.write_str("ERROR::NULL POINTER PASSED"),
+            LoggerError::InvalidUtf8 => fmt.write_str("ERROR::INVALID UTF8"),
+            LoggerError::InteriorNul => fmt.write_str("ERROR::CSTRING CONVERSION FAILED"),
+            LoggerError::Io(e) => write!(fmt, "ERROR::IO: {}", e),
+            LoggerError::ThreadPool => fmt.write_str("ERROR::THREAD POOL IS NOT RUNNING"),
+            LoggerError::ThreadId => fmt.write_str("ERROR::FAILED TO RESOLVE THREAD ID"),
+            LoggerError::LockPoisoned => fmt.write_str("ERROR::LOCK POISONED"),
+            LoggerError::InvalidLevel => fmt.write_str("ERROR::INVALID LOG LEVEL"),
+            LoggerError::AlreadyInitialized => fmt.write_str(crate::models::constants::LOGGER_ERROR_SET),
+            LoggerError::Unsupported(what) => write!(fmt, "ERROR::UNSUPPORTED: {}", what),
+            LoggerError::InvalidConfig(why) => write!(fmt, "ERROR::INVALID CONFIG: {}", why),
+            LoggerError::InvalidData(why) => write!(fmt, "ERROR::INVALID DATA: {}", why),
+            LoggerError::Panic(msg) => write!(fmt, "ERROR::PANIC: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for LoggerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoggerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoggerError {
+    fn from(e: io::Error) -> Self {
+        LoggerError::Io(e)
+    }
+}
+
+impl From<SetLoggerError> for LoggerError {
+    fn from(_: SetLoggerError) -> Self {
+        LoggerError::AlreadyInitialized
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for LoggerError {
+    fn from(_: std::sync::PoisonError<T>) -> Self {
+        LoggerError::LockPoisoned
+    }
+}
+
+impl From<std::str::Utf8Error> for LoggerError {
+    fn from(_: std::str::Utf8Error) -> Self {
+        LoggerError::InvalidUtf8
+    }
+}
+
+impl From<std::ffi::NulError> for LoggerError {
+    fn from(_: std::ffi::NulError) -> Self {
+        LoggerError::InteriorNul
+    }
+}
+
+/// Runs `f` and converts both its error and any panic into a code, so nothing
+/// ever unwinds across the `extern "C"` boundary.
+pub(crate) fn ffi_guard<F>(f: F) -> LoggerErrorCode
+where
+    F: FnOnce() -> Result<(), LoggerError> + UnwindSafe,
+{
+    match panic::catch_unwind(f) {
+        Ok(Ok(())) => LoggerErrorCode::Ok,
+        Ok(Err(e)) => {
+            eprintln!("{}", e);
+            e.code()
+        }
+        Err(payload) => {
+            let e = LoggerError::from_panic(payload);
+            eprintln!("{}", e);
+            e.code()
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::models::error::LoggerError;
+use crate::models::level::{Level, LevelFilter};
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Rings smaller than this could not hold a single ordinary record.
+pub const MIN_CAPACITY: u64 = 4096;
+
+const MAGIC: &[u8; 8] = b"ULRING01";
+const VERSION: u32 = 1;
+/// Magic, version, capacity, head, tail and next sequence number; the data area follows.
+const HEADER_LEN: u64 = 64;
+const CAPACITY_OFFSET: usize = 16;
+const HEAD_OFFSET: usize = 24;
+const TAIL_OFFSET: usize = 32;
+const SEQUENCE_OFFSET: usize = 40;
+/// Sequence number, timestamp, level, thread and source length.
+const FIXED_PAYLOAD_LEN: usize = 8 + 8 + 1 + 8 + 2;
+
+lazy_static::lazy_static! {
+    static ref RING: Mutex<Option<Ring>> = Mutex::new(None);
+}
+
+/// Keeps the most recent records in a fixed-size memory-mapped file. The
+/// mapping is shared, so whatever was written survives the process being
+/// killed; [`decode`] reads it back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlightRecorder {
+    pub(crate) path: PathBuf,
+    pub(crate) capacity: u64,
+    pub(crate) max_level: LevelFilter,
+}
+
+impl FlightRecorder {
+    /// Records everything down to `Trace` into the last `capacity` bytes at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P, capacity: u64) -> FlightRecorder {
+        FlightRecorder {
+            path: path.into(),
+            capacity,
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> FlightRecorder {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+}
+
+/// The mapped file. Offsets `head` and `tail` only ever grow; the data area
+/// is addressed modulo the capacity. Records are written at `head` after
+/// `tail` has been moved past every record they overwrite, and `head` is
+/// advanced last, so a process killed mid-record leaves a readable ring.
+struct Ring {
+    path: PathBuf,
+    capacity: u64,
+    map: *mut u8,
+    len: usize,
+}
+
+// The mapping is only touched with the `RING` lock held.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn open(path: &Path, capacity: u64) -> io::Result<Ring> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+        let len = HEADER_LEN + capacity;
+        // A ring left by an earlier run is kept going, so its records stay
+        // readable until they are overwritten. One whose header is damaged
+        // starts over.
+        let reusable = file.metadata()?.len() == len
+            && read_header(&file).is_ok_and(|header| header.capacity == capacity);
+        if !reusable {
+            file.set_len(0)?;
+            file.set_len(len)?;
+            file.write_all_at(MAGIC, 0)?;
+            file.write_all_at(&VERSION.to_le_bytes(), MAGIC.len() as u64)?;
+            file.write_all_at(&capacity.to_le_bytes(), CAPACITY_OFFSET as u64)?;
+        }
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Ring {
+            path: path.to_path_buf(),
+            capacity,
+            map: map.cast(),
+            len: len as usize,
+        })
+    }
+
+    fn counter(&self, offset: usize) -> &AtomicU64 {
+        // Header fields are 8-byte aligned within a page-aligned mapping.
+        unsafe { &*self.map.add(offset).cast::<AtomicU64>() }
+    }
+
+    fn data(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.map.add(HEADER_LEN as usize), self.capacity as usize) }
+    }
+
+    fn write(&mut self, level: Level, thread: u64, source: &str, message: &str) {
+        let sequence = self.counter(SEQUENCE_OFFSET).fetch_add(1, Ordering::Relaxed);
+        let record = encode(sequence, level, thread, source, message, self.capacity as usize);
+        let head = self.counter(HEAD_OFFSET).load(Ordering::Acquire);
+        let mut tail = self.counter(TAIL_OFFSET).load(Ordering::Acquire);
+        while head + record.len() as u64 - tail > self.capacity {
+            let mut len = [0u8; 4];
+            copy_out(self.data(), tail, &mut len);
+            // Clamped, so a ring damaged on disk cannot send us past the head.
+            tail = (tail + 4 + u32::from_le_bytes(len) as u64).min(head);
+        }
+        self.counter(TAIL_OFFSET).store(tail, Ordering::Release);
+        copy_in(self.data(), head, &record);
+        self.counter(HEAD_OFFSET).store(head + record.len() as u64, Ordering::Release);
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map.cast(), self.len);
+        }
+    }
+}
+
+fn copy_in(data: &mut [u8], offset: u64, bytes: &[u8]) {
+    let start = (offset % data.len() as u64) as usize;
+    let first = bytes.len().min(data.len() - start);
+    data[start..start + first].copy_from_slice(&bytes[..first]);
+    data[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+}
+
+fn copy_out(data: &[u8], offset: u64, bytes: &mut [u8]) {
+    let start = (offset % data.len() as u64) as usize;
+    let first = bytes.len().min(data.len() - start);
+    bytes[..first].copy_from_slice(&data[start..start + first]);
+    let rest = bytes.len() - first;
+    bytes[first..].copy_from_slice(&data[..rest]);
+}
+
+/// `u32` payload length, then the sequence number, microseconds since the
+/// epoch, level, thread, `u16` source length, source and message. Messages
+/// too long for the ring are cut short.
+fn encode(sequence: u64, level: Level, thread: u64, source: &str, message: &str, capacity: usize) -> Vec<u8> {
+    let source = &source.as_bytes()[..source.len().min(capacity / 4).min(u16::MAX as usize)];
+    let room = capacity.saturating_sub(4 + FIXED_PAYLOAD_LEN + source.len());
+    let message = &message.as_bytes()[..message.len().min(room)];
+    let len = FIXED_PAYLOAD_LEN + source.len() + message.len();
+    let mut bytes = Vec::with_capacity(4 + len);
+    bytes.extend_from_slice(&(len as u32).to_le_bytes());
+    bytes.extend_from_slice(&sequence.to_le_bytes());
+    bytes.extend_from_slice(&Utc::now().timestamp_micros().to_le_bytes());
+    bytes.push(level as u8);
+    bytes.extend_from_slice(&thread.to_le_bytes());
+    bytes.extend_from_slice(&(source.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(source);
+    bytes.extend_from_slice(message);
+    bytes
+}
+
+/// Writes one record into the ring `recorder` describes, opening or
+/// replacing the mapping if the configuration changed.
+pub(crate) fn record(recorder: &FlightRecorder, level: Level, thread: u64, source: &str, message: &str) {
+    let mut ring = RING.lock().unwrap_or_else(PoisonError::into_inner);
+    let current = ring
+        .as_ref()
+        .is_some_and(|ring| ring.path == recorder.path && ring.capacity == recorder.capacity);
+    if !current {
+        *ring = None;
+        match Ring::open(&recorder.path, recorder.capacity) {
+            Ok(opened) => *ring = Some(opened),
+            Err(e) => {
+                eprintln!("ERROR::OPENING FLIGHT RECORDER {}: {}", recorder.path.display(), e);
+                return;
+            }
+        }
+    }
+    if let Some(ring) = ring.as_mut() {
+        ring.write(level, thread, source, message);
+    }
+}
+
+/// Unmaps the ring, e.g. after the flight recorder was switched off.
+pub(crate) fn close() {
+    *RING.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// One record read back from a flight recorder file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RingRecord {
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    pub level: Level,
+    pub thread: u64,
+    pub source: String,
+    pub message: String,
+}
+
+impl fmt::Display for RingRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "#{} [{:?}] - [{}] - [{}] - [{}] - [{}]",
+            self.sequence,
+            self.level,
+            self.source,
+            self.message,
+            self.time.format("%Y-%m-%d %H:%M:%S%.6f"),
+            self.thread
+        )
+    }
+}
+
+struct Header {
+    capacity: u64,
+    head: u64,
+    tail: u64,
+}
+
+fn read_header(file: &File) -> Result<Header, LoggerError> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    file.read_exact_at(&mut header, 0)?;
+    let field = |offset: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&header[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    };
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(LoggerError::InvalidData("not a flight recorder file".to_string()));
+    }
+    let version = field(MAGIC.len()) as u32;
+    if version != VERSION {
+        return Err(LoggerError::InvalidData(format!("unsupported flight recorder version {}", version)));
+    }
+    let header = Header {
+        capacity: field(CAPACITY_OFFSET),
+        head: field(HEAD_OFFSET),
+        tail: field(TAIL_OFFSET),
+    };
+    let data_len = file.metadata()?.len().saturating_sub(HEADER_LEN);
+    if header.capacity == 0
+        || header.capacity > data_len
+        || header.tail > header.head
+        || header.head - header.tail > header.capacity
+    {
+        return Err(LoggerError::InvalidData("corrupt flight recorder header".to_string()));
+    }
+    Ok(header)
+}
+
+fn decode_record(payload: &[u8]) -> Option<RingRecord> {
+    let u64_at = |offset: usize| Some(u64::from_le_bytes(payload.get(offset..offset + 8)?.try_into().ok()?));
+    let source_len = u16::from_le_bytes(payload.get(25..27)?.try_into().ok()?) as usize;
+    let source = payload.get(FIXED_PAYLOAD_LEN..FIXED_PAYLOAD_LEN + source_len)?;
+    Some(RingRecord {
+        sequence: u64_at(0)?,
+        time: DateTime::from_timestamp_micros(u64_at(8)? as i64)?,
+        level: Level::from_usize(*payload.get(16)? as usize)?,
+        thread: u64_at(17)?,
+        source: String::from_utf8_lossy(source).into_owned(),
+        message: String::from_utf8_lossy(&payload[FIXED_PAYLOAD_LEN + source_len..]).into_owned(),
+    })
+}
+
+/// Reads every record still held in the flight recorder file at `path`,
+/// oldest first. Works on the file a crashed process left behind.
+pub fn decode(path: &Path) -> Result<Vec<RingRecord>, LoggerError> {
+    let file = File::open(path)?;
+    let header = read_header(&file)?;
+    let mut data = vec![0u8; header.capacity as usize];
+    file.read_exact_at(&mut data, HEADER_LEN)?;
+
+    let mut records = Vec::new();
+    let mut offset = header.tail;
+    while offset + 4 <= header.head {
+        let mut len = [0u8; 4];
+        copy_out(&data, offset, &mut len);
+        let len = u32::from_le_bytes(len) as u64;
+        if offset + 4 + len > header.head {
+            break;
+        }
+        let mut payload = vec![0u8; len as usize];
+        copy_out(&data, offset + 4, &mut payload);
+        match decode_record(&payload) {
+            Some(record) => records.push(record),
+            None => break,
+        }
+        offset += 4 + len;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damaged_ring_is_started_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flight.ring");
+        drop(Ring::open(&path, MIN_CAPACITY).unwrap());
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all_at(&100u64.to_le_bytes(), TAIL_OFFSET as u64).unwrap();
+        assert!(matches!(decode(&path), Err(LoggerError::InvalidData(_))));
+
+        let mut ring = Ring::open(&path, MIN_CAPACITY).unwrap();
+        ring.write(Level::Prod, 1, "app", "after the crash");
+        drop(ring);
+        let records = decode(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message, "after the crash");
+    }
+}
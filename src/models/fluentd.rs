@@ -0,0 +1,497 @@
+use crate::models::connection;
+use crate::models::error::LoggerError;
+use crate::models::format::level_name;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::msgpack::{self, put_array_len, put_bin, put_fixext8, put_map_len, put_str, put_uint};
+use crate::models::network::ConnectionState;
+use crate::models::sender::{self, Backoff, Batching, Sender};
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_TAG_PREFIX: &str = "common_logger";
+pub const DEFAULT_MAX_RECORDS: usize = 500;
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(1);
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// The extension type Fluentd reserves for `EventTime`.
+const EVENT_TIME: i8 = 0;
+
+static NEXT_CHUNK: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    static ref FORWARDER: sender::Slot<FluentdOutput> = Mutex::new(None);
+}
+
+/// The tag a record from `source` is forwarded under: `prefix.source`, with
+/// `::` turned into `.` and characters Fluentd routing chokes on into `_`.
+pub fn tag(prefix: &str, source: &str) -> String {
+    let source: String = source
+        .replace("::", ".")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') { c } else { '_' })
+        .collect();
+    let parts: Vec<&str> = [prefix, source.trim_matches('.')]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty() {
+        DEFAULT_TAG_PREFIX.to_string()
+    } else {
+        parts.join(".")
+    }
+}
+
+/// `record` as a Forward protocol entry, `[time, record]`: the time is an
+/// `EventTime` with nanoseconds, and the record a map with the `Json` file
+/// format's fields besides `timestamp`.
+pub fn entry(record: &LogData) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(128 + record.message.len());
+    put_array_len(&mut entry, 2);
+    let mut time = [0u8; 8];
+    time[..4].copy_from_slice(&(record.time.timestamp() as u32).to_be_bytes());
+    time[4..].copy_from_slice(&record.time.timestamp_subsec_nanos().to_be_bytes());
+    put_fixext8(&mut entry, EVENT_TIME, time);
+
+    let optional = [
+        ("thread_name", record.thread_name.as_deref()),
+        ("module_path", record.module_path.as_deref()),
+        ("file", record.file.as_deref()),
+    ];
+    let present = optional.iter().filter(|(_, value)| value.is_some()).count();
+    let len = 5 + present + record.line.is_some() as usize + !record.key_values.is_empty() as usize;
+    put_map_len(&mut entry, len);
+    put_str(&mut entry, "level");
+    put_str(&mut entry, level_name(record.level));
+    put_str(&mut entry, "source");
+    put_str(&mut entry, &record.source);
+    put_str(&mut entry, "message");
+    put_str(&mut entry, &record.message);
+    put_str(&mut entry, "thread_id");
+    put_uint(&mut entry, record.thread);
+    for (key, value) in optional {
+        if let Some(value) = value {
+            put_str(&mut entry, key);
+            put_str(&mut entry, value);
+        }
+    }
+    if let Some(line) = record.line {
+        put_str(&mut entry, "line");
+        put_uint(&mut entry, line as u64);
+    }
+    put_str(&mut entry, "sequence");
+    put_uint(&mut entry, record.sequence);
+    if !record.key_values.is_empty() {
+        put_str(&mut entry, "fields");
+        put_map_len(&mut entry, record.key_values.len());
+        for (key, value) in &record.key_values {
+            put_str(&mut entry, key);
+            put_str(&mut entry, value);
+        }
+    }
+    entry
+}
+
+/// A PackedForward message, `[tag, entries, {"size": n, "chunk": id}]`;
+/// `chunk` is left out when no acknowledgement is wanted.
+fn packed_forward(tag: &str, entries: &[&[u8]], chunk: Option<&str>) -> Vec<u8> {
+    let len = entries.iter().map(|entry| entry.len()).sum::<usize>();
+    let mut message = Vec::with_capacity(len + tag.len() + 64);
+    put_array_len(&mut message, 3);
+    put_str(&mut message, tag);
+    put_bin(&mut message, &entries.concat());
+    put_map_len(&mut message, 1 + chunk.is_some() as usize);
+    put_str(&mut message, "size");
+    put_uint(&mut message, entries.len() as u64);
+    if let Some(chunk) = chunk {
+        put_str(&mut message, "chunk");
+        put_str(&mut message, chunk);
+    }
+    message
+}
+
+/// A chunk id unique across processes and restarts, as 32 hex digits.
+fn chunk_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos() as u64)
+        .unwrap_or_default();
+    let counter = NEXT_CHUNK.fetch_add(1, Ordering::Relaxed) as u32;
+    format!("{:08x}{:016x}{:08x}", std::process::id(), nanos, counter)
+}
+
+/// Forwards records to a Fluentd or Fluent Bit `forward` input over TCP,
+/// from a thread of its own.
+///
+/// Records are batched per tag, see [`tag`], and sent in PackedForward mode
+/// once `max_records` are queued or the oldest has waited `max_delay`. With
+/// `require_ack` every message carries a chunk id and is sent again, on a
+/// new connection, unless the agent acknowledges it within `ack_timeout`;
+/// without it, records written just before the agent went away can be lost.
+/// While the agent cannot be reached, at most `queue_capacity` records are
+/// kept, the oldest dropped first, and the connection is retried with
+/// exponential backoff from 100 ms up to 30 s.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FluentdOutput {
+    pub(crate) address: String,
+    pub(crate) tag_prefix: String,
+    pub(crate) require_ack: bool,
+    pub(crate) max_records: usize,
+    pub(crate) max_delay: Duration,
+    pub(crate) ack_timeout: Duration,
+    pub(crate) queue_capacity: usize,
+    pub(crate) max_level: LevelFilter,
+}
+
+impl FluentdOutput {
+    /// Forwards to `address`, `host:port`, under tags starting with [`DEFAULT_TAG_PREFIX`].
+    pub fn new(address: &str) -> FluentdOutput {
+        FluentdOutput {
+            address: address.to_string(),
+            tag_prefix: DEFAULT_TAG_PREFIX.to_string(),
+            require_ack: false,
+            max_records: DEFAULT_MAX_RECORDS,
+            max_delay: DEFAULT_MAX_DELAY,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    /// What tags start with; empty tags records with their source alone.
+    pub fn tag_prefix(mut self, tag_prefix: &str) -> FluentdOutput {
+        self.tag_prefix = tag_prefix.trim_matches('.').to_string();
+        self
+    }
+
+    pub fn require_ack(mut self, require_ack: bool) -> FluentdOutput {
+        self.require_ack = require_ack;
+        self
+    }
+
+    pub fn max_records(mut self, max_records: usize) -> FluentdOutput {
+        self.max_records = max_records;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> FluentdOutput {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn ack_timeout(mut self, ack_timeout: Duration) -> FluentdOutput {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Records kept while the agent cannot be reached before the oldest are dropped.
+    pub fn queue_capacity(mut self, capacity: usize) -> FluentdOutput {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> FluentdOutput {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), LoggerError> {
+        if self.address.is_empty() {
+            return Err(LoggerError::InvalidConfig("Fluentd address is empty".to_string()));
+        }
+        if self.max_records == 0 || self.queue_capacity == 0 || self.ack_timeout.is_zero() {
+            return Err(LoggerError::InvalidConfig(
+                "Fluentd batch size, queue capacity and ack timeout must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How the Fluentd output is doing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FluentdStats {
+    pub address: String,
+    pub state: ConnectionState,
+    /// Records waiting for, or in, a message not yet sent or acknowledged.
+    pub queued: usize,
+    pub sent: u64,
+    /// Records dropped because the queue was full.
+    pub dropped: u64,
+    /// Connections established after the first one.
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+}
+
+/// A record waiting for the message to its tag.
+pub(crate) struct Queued {
+    tag: String,
+    entry: Vec<u8>,
+}
+
+/// Reads the agent's `{"ack": chunk}` answer and checks it names `chunk`.
+fn await_ack(stream: &mut TcpStream, chunk: &str, timeout: Duration) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 256];
+    loop {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "agent closed the connection before acknowledging"));
+        }
+        response.extend_from_slice(&buffer[..read]);
+        match msgpack::decode(&response) {
+            Ok((answer, _)) => {
+                return match answer.get("ack").and_then(msgpack::Value::as_str) {
+                    Some(ack) if ack == chunk => Ok(()),
+                    _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected answer {:?}", answer))),
+                };
+            }
+            Err(msgpack::DecodeError::Incomplete) => continue,
+            Err(msgpack::DecodeError::Invalid(reason)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, reason));
+            }
+        }
+    }
+}
+
+/// Sends `batch` one message per tag, in the order each tag first appears,
+/// and takes what was delivered off it.
+fn deliver(sender: &Sender<FluentdOutput>, stream: &mut TcpStream, batch: &mut Vec<(Instant, Queued)>) -> io::Result<()> {
+    while let Some((_, first)) = batch.first() {
+        let tag = first.tag.clone();
+        let (group, rest): (Vec<_>, Vec<_>) = batch.drain(..).partition(|(_, record)| record.tag == tag);
+        *batch = rest;
+        let entries: Vec<&[u8]> = group.iter().map(|(_, record)| record.entry.as_slice()).collect();
+        let chunk = sender.output.require_ack.then(chunk_id);
+        let message = packed_forward(&tag, &entries, chunk.as_deref());
+        let sent = stream.write_all(&message).and_then(|()| match &chunk {
+            Some(chunk) => await_ack(stream, chunk, sender.output.ack_timeout),
+            None => Ok(()),
+        });
+        if let Err(e) = sent {
+            // Cut short, or arrived unacknowledged; it goes again, whole.
+            batch.extend(group);
+            return Err(e);
+        }
+        sender.delivered(group.len());
+    }
+    Ok(())
+}
+
+impl sender::Output for FluentdOutput {
+    type Record = Queued;
+    const KIND: &'static str = "fluentd";
+
+    fn destination(&self) -> String {
+        self.address.clone()
+    }
+
+    fn queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    fn batching(&self) -> Batching {
+        Batching {
+            max_records: self.max_records,
+            max_bytes: usize::MAX,
+            max_delay: self.max_delay,
+        }
+    }
+
+    fn record_len(record: &Queued) -> usize {
+        record.entry.len()
+    }
+
+    fn run(sender: Arc<Sender<FluentdOutput>>) {
+        let connect = |output: &FluentdOutput| connection::tcp(&output.address);
+        sender::run_connected(&sender, Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF), connect, deliver);
+    }
+}
+
+/// Queues `record` for the next message to its tag; never waits for the agent.
+pub(crate) fn send(output: &FluentdOutput, record: &LogData) {
+    if output.accepts(record.level) {
+        let queued = Queued {
+            tag: tag(&output.tag_prefix, &record.source),
+            entry: entry(record),
+        };
+        sender::enqueue(&FORWARDER, output, queued);
+    }
+}
+
+/// Sends every pending record now, waiting until `deadline` for the agent
+/// to take them, as long as it can be reached.
+pub(crate) fn flush(deadline: Instant) {
+    sender::flush(&FORWARDER, deadline);
+}
+
+/// Stops the sender thread once it has made one last attempt at what it still holds.
+pub(crate) fn close() {
+    sender::close(&FORWARDER);
+}
+
+/// Stops the sender thread, waiting until `deadline` for what it still holds to go out.
+pub(crate) fn shut_down(deadline: Instant) {
+    sender::shut_down(&FORWARDER, deadline);
+}
+
+/// The current sender's numbers, if a Fluentd output has been used.
+pub(crate) fn stats() -> Option<FluentdStats> {
+    sender::stats(&FORWARDER, |sender, queue| FluentdStats {
+        address: sender.output.address.clone(),
+        state: queue.state,
+        queued: queue.queued(),
+        sent: sender.sent.load(Ordering::Relaxed),
+        dropped: sender.dropped.load(Ordering::Relaxed),
+        reconnects: sender.reconnects.load(Ordering::Relaxed),
+        last_error: queue.last_error.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::msgpack::Value;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn record(source: &str, message: &str) -> LogData {
+        LogData {
+            thread_name: Some("worker".to_string()),
+            line: Some(42),
+            ..LogData::test(Level::Prod, source, message).key_values(&[("user", "alice")])
+        }
+    }
+
+    /// The next whole value the client sent, or `None` once it hung up.
+    fn read_value(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<Value> {
+        loop {
+            match msgpack::decode(buffer) {
+                Ok((value, len)) => {
+                    buffer.drain(..len);
+                    return Some(value);
+                }
+                Err(msgpack::DecodeError::Incomplete) => {}
+                Err(e) => panic!("{:?}", e),
+            }
+            let mut read = [0u8; 4096];
+            match stream.read(&mut read).unwrap() {
+                0 => return None,
+                len => buffer.extend_from_slice(&read[..len]),
+            }
+        }
+    }
+
+    /// A PackedForward message's tag, its entries and its options.
+    fn unpack(message: Value) -> (String, Vec<Value>, Value) {
+        let Value::Array(mut parts) = message else {
+            panic!("not an array: {:?}", message);
+        };
+        let options = parts.pop().unwrap();
+        let Some(Value::Bin(packed)) = parts.pop() else {
+            panic!("entries are not a bin");
+        };
+        let tag = parts.pop().and_then(|tag| tag.as_str().map(str::to_string)).unwrap();
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset < packed.len() {
+            let (entry, len) = msgpack::decode(&packed[offset..]).unwrap();
+            entries.push(entry);
+            offset += len;
+        }
+        (tag, entries, options)
+    }
+
+    #[test]
+    fn tags_are_prefixed_and_sanitized() {
+        assert_eq!(tag("app", "db::pool"), "app.db.pool");
+        assert_eq!(tag("", "db pool!"), "db_pool_");
+        assert_eq!(tag("", "::"), DEFAULT_TAG_PREFIX);
+    }
+
+    #[test]
+    fn packed_forward_is_sent_again_until_acknowledged() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (messages, received) = mpsc::channel();
+        thread::spawn(move || {
+            // The first agent takes a message and goes away without acknowledging it.
+            let (mut stream, _) = listener.accept().unwrap();
+            read_value(&mut stream, &mut Vec::new()).unwrap();
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = Vec::new();
+            while let Some(message) = read_value(&mut stream, &mut buffer) {
+                let (tag, entries, options) = unpack(message);
+                let chunk = options.get("chunk").and_then(Value::as_str).unwrap().to_string();
+                let mut ack = Vec::new();
+                put_map_len(&mut ack, 1);
+                put_str(&mut ack, "ack");
+                put_str(&mut ack, &chunk);
+                stream.write_all(&ack).unwrap();
+                messages.send((tag, entries, options)).unwrap();
+            }
+        });
+
+        let output = FluentdOutput::new(&address)
+            .tag_prefix("test")
+            .require_ack(true)
+            .max_records(3)
+            .max_delay(Duration::from_secs(60));
+        send(&output, &record("app::db", "first"));
+        send(&output, &record("app::http", "second"));
+        send(&output, &record("app::db", "third"));
+
+        let (tag, entries, options) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(tag, "test.app.db");
+        assert_eq!(options.get("size"), Some(&Value::UInt(2)));
+        let messages: Vec<&str> = entries
+            .iter()
+            .map(|entry| {
+                let Value::Array(parts) = entry else {
+                    panic!("entry is not an array: {:?}", entry);
+                };
+                let mut seconds = 1_709_622_489u32.to_be_bytes().to_vec();
+                seconds.extend_from_slice(&[0; 4]);
+                assert_eq!(parts[0], Value::Ext(EVENT_TIME, seconds));
+                assert_eq!(parts[1].get("level").and_then(Value::as_str), Some("prod"));
+                assert_eq!(parts[1].get("thread_name").and_then(Value::as_str), Some("worker"));
+                assert_eq!(parts[1].get("line"), Some(&Value::UInt(42)));
+                let fields = parts[1].get("fields").unwrap();
+                assert_eq!(fields.get("user").and_then(Value::as_str), Some("alice"));
+                parts[1].get("message").and_then(Value::as_str).unwrap()
+            })
+            .collect();
+        assert_eq!(messages, ["first", "third"]);
+
+        let (tag, entries, _) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(tag, "test.app.http");
+        assert_eq!(entries.len(), 1);
+
+        flush(Instant::now() + Duration::from_secs(5));
+        let stats = stats().unwrap();
+        assert_eq!((stats.sent, stats.queued, stats.reconnects), (3, 0, 1));
+        assert_eq!(stats.state, ConnectionState::Connected);
+        close();
+    }
+}
@@ -0,0 +1,188 @@
+use crate::models::binary::{self, Interner};
+use crate::models::constants::DATE_FORMAT;
+use crate::models::ecs;
+use crate::models::error::LoggerError;
+use crate::models::gelf;
+use crate::models::level::Level;
+use crate::models::logfmt;
+use crate::models::logger::LogData;
+use crate::models::otel;
+use crate::models::schedule::LogTimeZone;
+use crate::models::service::ServiceInfo;
+use crate::models::template::Template;
+use chrono::SecondsFormat;
+use serde::{Serialize, Serializer};
+
+/// How records are laid out in log files.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum LogFormat {
+    /// The padded, human-readable lines the logger always wrote.
+    #[default]
+    Text,
+    /// Compact varint-framed records; see [`binary`] for the layout and the decoder.
+    Binary,
+    /// One JSON object per line, see [`json_line`] for the field names.
+    Json,
+    /// `key=value` pairs, one record per line; see [`logfmt`].
+    Logfmt,
+    /// GELF 1.1 messages, one per line, as the GELF output sends them; see [`gelf::message`].
+    Gelf,
+    /// One OTLP JSON export request per line, for the Collector's
+    /// `otlpjsonfile` receiver; see [`otel::export_line`].
+    Otlp,
+    /// Elastic Common Schema JSON, one object per line; see [`ecs::line`].
+    Ecs,
+}
+
+impl LogFormat {
+    /// What a new, empty file has to start with.
+    pub fn file_header(&self) -> Vec<u8> {
+        match self {
+            LogFormat::Binary => binary::header(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the format can be printed to a terminal.
+    pub fn is_textual(&self) -> bool {
+        !matches!(self, LogFormat::Binary)
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = LoggerError;
+
+    fn from_str(format: &str) -> Result<LogFormat, Self::Err> {
+        match format.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "binary" => Ok(LogFormat::Binary),
+            "json" | "jsonl" => Ok(LogFormat::Json),
+            "logfmt" => Ok(LogFormat::Logfmt),
+            "gelf" => Ok(LogFormat::Gelf),
+            "otlp" | "otel" => Ok(LogFormat::Otlp),
+            "ecs" => Ok(LogFormat::Ecs),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown log format `{}`", format))),
+        }
+    }
+}
+
+/// The classic text line for `record`, key-value pairs appended when it has any.
+pub fn text_line(record: &LogData, timezone: LogTimeZone) -> String {
+    let date = timezone.convert(record.time).format(DATE_FORMAT).to_string();
+    let mut line = format!(
+        "[{:?}] - [{:<60}] - [{:<30}] - [{:<19}] - [{}]",
+        record.level,
+        record.source.trim(),
+        record.message.trim(),
+        date.trim(),
+        record.thread
+    );
+    if !record.key_values.is_empty() {
+        let pairs: Vec<String> = record
+            .key_values
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        line.push_str(&format!(" - [{}]", pairs.join(", ")));
+    }
+    line
+}
+
+/// Lowercase level names, as the structured formats write them.
+pub fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Prod => "prod",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+/// The JSON Lines layout. Field names and order are part of the format;
+/// fields without a value are left out rather than written as `null`.
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: String,
+    level: &'static str,
+    source: &'a str,
+    message: &'a str,
+    thread_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module_path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    sequence: u64,
+    #[serde(skip_serializing_if = "<[_]>::is_empty", serialize_with = "as_map")]
+    fields: &'a [(String, String)],
+}
+
+fn as_map<S: Serializer>(pairs: &&[(String, String)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(pairs.iter().map(|(key, value)| (key, value)))
+}
+
+/// `record` as one line of JSON, without the newline. Timestamps are RFC 3339
+/// in UTC with microseconds; key-value pairs go under `fields`.
+pub fn json_line(record: &LogData) -> String {
+    let json = JsonRecord {
+        timestamp: record.time.to_rfc3339_opts(SecondsFormat::Micros, true),
+        level: level_name(record.level),
+        source: &record.source,
+        message: &record.message,
+        thread_id: record.thread,
+        thread_name: record.thread_name.as_deref(),
+        module_path: record.module_path.as_deref(),
+        file: record.file.as_deref(),
+        line: record.line,
+        sequence: record.sequence,
+        fields: &record.key_values,
+    };
+    // Strings and numbers always serialize; there is no error to report.
+    serde_json::to_string(&json).unwrap_or_default()
+}
+
+/// `record` as one line of a textual `format`, without the newline; `None`
+/// for formats that are not line-based. `template`, if any, replaces the
+/// built-in layout of `Text`; `service` names the writer in `Ecs` lines.
+pub fn line(
+    format: LogFormat,
+    template: Option<&Template>,
+    record: &LogData,
+    timezone: LogTimeZone,
+    service: &ServiceInfo,
+) -> Option<String> {
+    match format {
+        LogFormat::Text => Some(match template {
+            Some(template) => template.render(record, timezone),
+            None => text_line(record, timezone),
+        }),
+        LogFormat::Binary => None,
+        LogFormat::Json => Some(json_line(record)),
+        LogFormat::Logfmt => Some(logfmt::render(record)),
+        LogFormat::Gelf => Some(gelf::message(record)),
+        LogFormat::Otlp => Some(otel::export_line(record)),
+        LogFormat::Ecs => Some(ecs::line(record, service)),
+    }
+}
+
+/// `record` as `format` lays it out in a file, interning into `interner`
+/// where the format supports it.
+pub(crate) fn encode(
+    format: LogFormat,
+    template: Option<&Template>,
+    record: &LogData,
+    timezone: LogTimeZone,
+    service: &ServiceInfo,
+    interner: Option<&mut Interner>,
+) -> Vec<u8> {
+    match line(format, template, record, timezone, service) {
+        Some(mut line) => {
+            line.push('\n');
+            line.into_bytes()
+        }
+        None => binary::encode(record, interner),
+    }
+}
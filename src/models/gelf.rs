@@ -0,0 +1,277 @@
+use crate::models::connection::{self, Connection, Transport};
+use crate::models::error::LoggerError;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::naming::HOSTNAME;
+use crate::models::syslog::severity;
+use flate2::write::GzEncoder;
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Chunk size Graylog recommends for UDP across networks of unknown MTU.
+pub const DEFAULT_CHUNK_SIZE: usize = 1420;
+/// A message may be split into at most this many UDP chunks.
+const MAX_CHUNKS: usize = 128;
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+/// Magic, message id, sequence number and count.
+const CHUNK_HEADER_LEN: usize = 12;
+
+static NEXT_MESSAGE_ID: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    static ref CONNECTION: Mutex<Connection<GelfTransport>> = Mutex::new(Connection::default());
+}
+
+/// Graylog refuses additional field names outside `[\w.-]`, and `_id`.
+fn field_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') { c } else { '_' })
+        .collect();
+    match name.as_str() {
+        "" | "id" => format!("_{}_", name),
+        _ => format!("_{}", name),
+    }
+}
+
+/// `record` as a GELF 1.1 message: the first line of the message as
+/// `short_message`, `-` if that is blank, the whole of it as `full_message` when it has several,
+/// the syslog severity as `level`, and `_source`, `_thread`, `_thread_name`,
+/// `_module`, `_file` and `_line`. Key-value pairs follow as `_key`; a pair
+/// whose name is taken is sent as `_kv_key` instead.
+pub fn message(record: &LogData) -> String {
+    let mut fields = Map::new();
+    fields.insert("version".to_string(), Value::from("1.1"));
+    fields.insert("host".to_string(), Value::from(HOSTNAME.as_str()));
+    // Graylog refuses messages with an empty `short_message`.
+    let short = record
+        .message
+        .lines()
+        .next()
+        .filter(|line| !line.trim().is_empty())
+        .unwrap_or("-");
+    fields.insert("short_message".to_string(), Value::from(short));
+    if short.len() < record.message.trim_end().len() {
+        fields.insert("full_message".to_string(), Value::from(record.message.as_str()));
+    }
+    let seconds = record.time.timestamp() as f64 + record.time.timestamp_subsec_micros() as f64 / 1e6;
+    fields.insert("timestamp".to_string(), Value::from(seconds));
+    fields.insert("level".to_string(), Value::from(severity(record.level)));
+    fields.insert("_source".to_string(), Value::from(record.source.as_str()));
+    fields.insert("_thread".to_string(), Value::from(record.thread));
+    if let Some(name) = &record.thread_name {
+        fields.insert("_thread_name".to_string(), Value::from(name.as_str()));
+    }
+    if let Some(module_path) = &record.module_path {
+        fields.insert("_module".to_string(), Value::from(module_path.as_str()));
+    }
+    if let Some(file) = &record.file {
+        fields.insert("_file".to_string(), Value::from(file.as_str()));
+    }
+    if let Some(line) = record.line {
+        fields.insert("_line".to_string(), Value::from(line));
+    }
+    for (key, value) in &record.key_values {
+        let mut name = field_name(key);
+        if fields.contains_key(&name) {
+            name = format!("_kv{}", name);
+        }
+        fields.insert(name, Value::from(value.as_str()));
+    }
+    Value::Object(fields).to_string()
+}
+
+/// How messages reach Graylog.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GelfTransport {
+    /// Datagrams to `host:port`, split into chunks when too large for one.
+    Udp(String),
+    /// A stream to `host:port`, each message ended by a null byte.
+    Tcp(String),
+}
+
+/// The [`GelfTransport`] variants as seen from C; the address travels separately.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GelfTransportKind {
+    Udp,
+    Tcp,
+}
+
+impl GelfTransport {
+    pub fn new(kind: GelfTransportKind, address: &str) -> Result<GelfTransport, LoggerError> {
+        if address.is_empty() {
+            return Err(LoggerError::InvalidConfig("GELF address is empty".to_string()));
+        }
+        Ok(match kind {
+            GelfTransportKind::Udp => GelfTransport::Udp(address.to_string()),
+            GelfTransportKind::Tcp => GelfTransport::Tcp(address.to_string()),
+        })
+    }
+}
+
+impl FromStr for GelfTransportKind {
+    type Err = LoggerError;
+
+    fn from_str(kind: &str) -> Result<GelfTransportKind, Self::Err> {
+        match kind.to_ascii_lowercase().as_str() {
+            "udp" => Ok(GelfTransportKind::Udp),
+            "tcp" => Ok(GelfTransportKind::Tcp),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown GELF transport `{}`", kind))),
+        }
+    }
+}
+
+/// Sends records to Graylog as GELF 1.1 messages, see [`message`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GelfOutput {
+    pub(crate) transport: GelfTransport,
+    pub(crate) compress: bool,
+    pub(crate) chunk_size: usize,
+    pub(crate) max_level: LevelFilter,
+}
+
+impl GelfOutput {
+    pub fn new(transport: GelfTransport) -> GelfOutput {
+        GelfOutput {
+            transport,
+            compress: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    /// Gzips UDP messages; GELF over TCP cannot be compressed.
+    pub fn compress(mut self, compress: bool) -> GelfOutput {
+        self.compress = compress;
+        self
+    }
+
+    /// The largest UDP datagram sent, chunk header included.
+    pub fn chunk_size(mut self, chunk_size: usize) -> GelfOutput {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> GelfOutput {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn transport(&self) -> &GelfTransport {
+        &self.transport
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), LoggerError> {
+        if self.chunk_size <= CHUNK_HEADER_LEN {
+            return Err(LoggerError::InvalidConfig(format!(
+                "GELF chunk size must be greater than {} bytes",
+                CHUNK_HEADER_LEN
+            )));
+        }
+        if self.compress && matches!(self.transport, GelfTransport::Tcp(_)) {
+            return Err(LoggerError::InvalidConfig("GELF over TCP cannot be compressed".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Splits `message` into chunked datagrams of at most `chunk_size` bytes,
+/// or returns it whole if it fits in one.
+fn chunks(message: Vec<u8>, chunk_size: usize) -> io::Result<Vec<Vec<u8>>> {
+    if message.len() <= chunk_size {
+        return Ok(vec![message]);
+    }
+    let payload = chunk_size - CHUNK_HEADER_LEN;
+    let count = message.len().div_ceil(payload);
+    if count > MAX_CHUNKS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("message of {} bytes needs more than {} chunks", message.len(), MAX_CHUNKS),
+        ));
+    }
+    let id = NEXT_MESSAGE_ID.fetch_add(1, Ordering::Relaxed) ^ ((std::process::id() as u64) << 32);
+    Ok(message
+        .chunks(payload)
+        .enumerate()
+        .map(|(sequence, part)| {
+            let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + part.len());
+            chunk.extend_from_slice(&CHUNK_MAGIC);
+            chunk.extend_from_slice(&id.to_be_bytes());
+            chunk.push(sequence as u8);
+            chunk.push(count as u8);
+            chunk.extend_from_slice(part);
+            chunk
+        })
+        .collect())
+}
+
+pub(crate) enum Socket {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Socket {
+    fn send(&mut self, output: &GelfOutput, message: &str) -> io::Result<()> {
+        match self {
+            Socket::Udp(socket) => {
+                let mut bytes = message.as_bytes().to_vec();
+                if output.compress {
+                    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(&bytes)?;
+                    bytes = encoder.finish()?;
+                }
+                for chunk in chunks(bytes, output.chunk_size)? {
+                    socket.send(&chunk)?;
+                }
+                Ok(())
+            }
+            Socket::Tcp(stream) => {
+                let mut frame = Vec::with_capacity(message.len() + 1);
+                frame.extend_from_slice(message.as_bytes());
+                frame.push(0);
+                stream.write_all(&frame)
+            }
+        }
+    }
+}
+
+impl Transport for GelfTransport {
+    type Socket = Socket;
+
+    fn connect(&self) -> io::Result<Socket> {
+        match self {
+            GelfTransport::Udp(address) => Ok(Socket::Udp(connection::udp(address)?)),
+            GelfTransport::Tcp(address) => Ok(Socket::Tcp(connection::tcp(address)?)),
+        }
+    }
+}
+
+/// Sends `record` to the Graylog input `output` describes, connecting first
+/// if needed. While it cannot be reached, records are dropped rather than queued.
+pub(crate) fn send(output: &GelfOutput, record: &LogData) {
+    if !output.accepts(record.level) {
+        return;
+    }
+    let message = message(record);
+    let mut connection = CONNECTION.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(e) = connection.send(&output.transport, |socket| socket.send(output, &message)) {
+        eprintln!("ERROR::SENDING GELF TO {:?}: {}", output.transport, e);
+    }
+}
+
+/// Closes the connection, e.g. after the GELF output was switched off.
+pub(crate) fn close() {
+    *CONNECTION.lock().unwrap_or_else(PoisonError::into_inner) = Connection::default();
+}
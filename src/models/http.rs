@@ -0,0 +1,560 @@
+use crate::models::error::LoggerError;
+use crate::models::format::json_line;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::otel;
+use crate::models::sender::{self, Backoff, Batching, Sender};
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_RECORDS: usize = 500;
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Where a sender thread is kept; outputs built on this one keep their own.
+pub(crate) type Batcher = sender::Slot<HttpOutput>;
+
+lazy_static::lazy_static! {
+    static ref BATCHER: Batcher = Mutex::new(None);
+}
+
+/// How a batch is laid out in the request body.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum BatchEncoding {
+    /// One JSON array of records, `application/json`.
+    #[default]
+    JsonArray,
+    /// One JSON record per line, `application/x-ndjson`.
+    Ndjson,
+    /// An OTLP/HTTP JSON export request, for a `/v1/logs` endpoint; see
+    /// [`otel::log_record`] for how records map onto OpenTelemetry's.
+    Otlp,
+}
+
+impl FromStr for BatchEncoding {
+    type Err = LoggerError;
+
+    fn from_str(encoding: &str) -> Result<BatchEncoding, Self::Err> {
+        match encoding.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "jsonarray" | "json" | "array" => Ok(BatchEncoding::JsonArray),
+            "ndjson" | "jsonlines" => Ok(BatchEncoding::Ndjson),
+            "otlp" | "otel" => Ok(BatchEncoding::Otlp),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown batch encoding `{}`", encoding))),
+        }
+    }
+}
+
+impl BatchEncoding {
+    fn content_type(&self) -> &'static str {
+        match self {
+            BatchEncoding::JsonArray | BatchEncoding::Otlp => "application/json",
+            BatchEncoding::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// What one queued record is: a JSON record, or for `Otlp` a `LogRecord`
+    /// with its scope.
+    fn record(&self, record: &LogData) -> Vec<u8> {
+        match self {
+            BatchEncoding::JsonArray | BatchEncoding::Ndjson => json_line(record).into_bytes(),
+            BatchEncoding::Otlp => otel::scoped_log_record(record),
+        }
+    }
+
+    fn body(&self, records: &[Vec<u8>]) -> Vec<u8> {
+        let len = records.iter().map(|record| record.len() + 1).sum::<usize>() + 2;
+        let mut body = Vec::with_capacity(len);
+        match self {
+            BatchEncoding::JsonArray => {
+                body.push(b'[');
+                for (index, record) in records.iter().enumerate() {
+                    if index > 0 {
+                        body.push(b',');
+                    }
+                    body.extend_from_slice(record);
+                }
+                body.push(b']');
+            }
+            BatchEncoding::Ndjson => {
+                for record in records {
+                    body.extend_from_slice(record);
+                    body.push(b'\n');
+                }
+            }
+            BatchEncoding::Otlp => return otel::export_request(records),
+        }
+        body
+    }
+}
+
+/// POSTs records to an HTTP ingestion endpoint in batches, from a thread of its own.
+///
+/// A batch is sent once it holds `max_records` records or `max_bytes` of
+/// them, or its oldest record has waited `max_delay`. Records are those of
+/// the `Json` file format, or OpenTelemetry log records with
+/// [`BatchEncoding::Otlp`], which makes this an OTLP/HTTP exporter.
+/// Timeouts, connection failures, 429 and 5xx responses are retried up to
+/// `max_retries` times with exponential backoff; other responses drop the
+/// batch. Pending batches are sent on `flush`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpOutput {
+    pub(crate) url: String,
+    pub(crate) encoding: BatchEncoding,
+    pub(crate) gzip: bool,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) max_records: usize,
+    pub(crate) max_bytes: usize,
+    pub(crate) max_delay: Duration,
+    pub(crate) max_retries: u32,
+    pub(crate) timeout: Duration,
+    pub(crate) queue_capacity: usize,
+    pub(crate) max_level: LevelFilter,
+}
+
+impl HttpOutput {
+    pub fn new(url: &str) -> HttpOutput {
+        HttpOutput {
+            url: url.to_string(),
+            encoding: BatchEncoding::default(),
+            gzip: false,
+            headers: Vec::new(),
+            max_records: DEFAULT_MAX_RECORDS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_delay: DEFAULT_MAX_DELAY,
+            max_retries: DEFAULT_MAX_RETRIES,
+            timeout: DEFAULT_TIMEOUT,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    pub fn encoding(mut self, encoding: BatchEncoding) -> HttpOutput {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Compresses request bodies, sent with `Content-Encoding: gzip`.
+    pub fn gzip(mut self, gzip: bool) -> HttpOutput {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Sends `name: value` with every request, e.g. an `Authorization` token.
+    pub fn header(mut self, name: &str, value: &str) -> HttpOutput {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn max_records(mut self, max_records: usize) -> HttpOutput {
+        self.max_records = max_records;
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> HttpOutput {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> HttpOutput {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> HttpOutput {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// How long one request may take, connecting included.
+    pub fn timeout(mut self, timeout: Duration) -> HttpOutput {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Records kept while batches cannot be sent before the oldest are dropped.
+    pub fn queue_capacity(mut self, capacity: usize) -> HttpOutput {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> HttpOutput {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), LoggerError> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(LoggerError::InvalidConfig(format!("`{}` is not an http(s) URL", self.url)));
+        }
+        if self.max_records == 0 || self.max_bytes == 0 || self.queue_capacity == 0 {
+            return Err(LoggerError::InvalidConfig(
+                "HTTP batch limits and queue capacity must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How the HTTP output is doing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HttpStats {
+    pub url: String,
+    /// Records waiting for, or in, a batch that has not been sent yet.
+    pub queued: usize,
+    pub sent_batches: u64,
+    pub sent_records: u64,
+    /// Batches given up on after a refusal or too many retries.
+    pub failed_batches: u64,
+    /// Records dropped because the queue was full or their batch failed.
+    pub dropped: u64,
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+enum Outcome {
+    Sent(u16),
+    Retry(Option<u16>, String),
+    Refused(Option<u16>, String),
+}
+
+/// An agent whose requests give up after `output`'s timeout.
+pub(crate) fn agent(output: &HttpOutput) -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(output.timeout).build()
+}
+
+fn post(agent: &ureq::Agent, output: &HttpOutput, body: &[u8]) -> Outcome {
+    let mut request = agent.post(&output.url).set("Content-Type", output.encoding.content_type());
+    if output.gzip {
+        request = request.set("Content-Encoding", "gzip");
+    }
+    for (name, value) in &output.headers {
+        request = request.set(name, value);
+    }
+    match request.send_bytes(body) {
+        Ok(response) => Outcome::Sent(response.status()),
+        Err(ureq::Error::Status(status, response)) => {
+            let reason = format!("HTTP {} {}", status, response.status_text());
+            if status == 429 || status >= 500 {
+                Outcome::Retry(Some(status), reason)
+            } else {
+                Outcome::Refused(Some(status), reason)
+            }
+        }
+        Err(ureq::Error::Transport(e)) => Outcome::Retry(None, e.to_string()),
+    }
+}
+
+/// POSTs `body`, a batch of `records` records taken off `sender`'s queue,
+/// as `output` says, retrying as it allows; outputs built on this one
+/// send their batches through here.
+pub(crate) fn post_batch<O: sender::Output>(
+    sender: &Sender<O>,
+    output: &HttpOutput,
+    agent: &ureq::Agent,
+    mut body: Vec<u8>,
+    records: usize,
+) {
+    if output.gzip {
+        let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), flate2::Compression::default());
+        // Writing to a `Vec` cannot fail.
+        let _ = encoder.write_all(&body);
+        body = encoder.finish().unwrap_or_default();
+    }
+    let mut backoff = Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF);
+    let mut attempt = 0;
+    loop {
+        match post(agent, output, &body) {
+            Outcome::Sent(status) => {
+                sender.lock().last_status = Some(status);
+                sender.sent_batches.fetch_add(1, Ordering::Relaxed);
+                sender.delivered(records);
+                return;
+            }
+            Outcome::Retry(status, e) if attempt < output.max_retries && !sender.is_stopped() => {
+                let mut queue = sender.lock();
+                queue.last_status = status.or(queue.last_status);
+                queue.last_error = Some(e);
+                drop(queue);
+                sender.back_off(&mut backoff);
+                attempt += 1;
+            }
+            Outcome::Retry(status, e) | Outcome::Refused(status, e) => {
+                eprintln!("ERROR::POSTING LOG BATCH TO {}: {}", output.url, e);
+                let mut queue = sender.lock();
+                queue.last_status = status.or(queue.last_status);
+                queue.last_error = Some(e);
+                drop(queue);
+                sender.failed_batches.fetch_add(1, Ordering::Relaxed);
+                sender.give_up(records);
+                return;
+            }
+        }
+    }
+}
+
+impl sender::Output for HttpOutput {
+    type Record = Vec<u8>;
+    const KIND: &'static str = "http";
+
+    fn destination(&self) -> String {
+        self.url.clone()
+    }
+
+    fn queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    fn batching(&self) -> Batching {
+        Batching {
+            max_records: self.max_records,
+            max_bytes: self.max_bytes,
+            max_delay: self.max_delay,
+        }
+    }
+
+    fn record_len(record: &Vec<u8>) -> usize {
+        record.len()
+    }
+
+    /// Sends batches as they fall due, until the output is replaced or
+    /// switched off; whatever is still queued then is sent before the thread ends.
+    fn run(sender: Arc<Sender<HttpOutput>>) {
+        let agent = agent(&sender.output);
+        while let Some(batch) = sender.next_batch() {
+            let records: Vec<Vec<u8>> = batch.into_iter().map(|(_, record)| record).collect();
+            let body = sender.output.encoding.body(&records);
+            post_batch(&sender, &sender.output, &agent, body, records.len());
+        }
+    }
+}
+
+/// Queues `record` for the next batch; never waits for the endpoint.
+pub(crate) fn send(output: &HttpOutput, record: &LogData) {
+    if output.accepts(record.level) {
+        sender::enqueue(&BATCHER, output, output.encoding.record(record));
+    }
+}
+
+/// Sends every pending record now, waiting until `deadline` for the batches to go out.
+pub(crate) fn flush(deadline: Instant) {
+    sender::flush(&BATCHER, deadline);
+}
+
+/// Stops the sender thread once it has sent what it still holds.
+pub(crate) fn close() {
+    sender::close(&BATCHER);
+}
+
+/// Stops the sender thread, waiting until `deadline` for what it still holds to go out.
+pub(crate) fn shut_down(deadline: Instant) {
+    sender::shut_down(&BATCHER, deadline);
+}
+
+/// The current sender's numbers, if an HTTP output has been used.
+pub(crate) fn stats() -> Option<HttpStats> {
+    batcher_stats(&BATCHER)
+}
+
+pub(crate) fn batcher_stats<O: sender::Output>(slot: &sender::Slot<O>) -> Option<HttpStats> {
+    sender::stats(slot, |sender, queue| HttpStats {
+        url: sender.output.destination(),
+        queued: queue.queued(),
+        sent_batches: sender.sent_batches.load(Ordering::Relaxed),
+        sent_records: sender.sent.load(Ordering::Relaxed),
+        failed_batches: sender.failed_batches.load(Ordering::Relaxed),
+        dropped: sender.dropped.load(Ordering::Relaxed),
+        last_status: queue.last_status,
+        last_error: queue.last_error.clone(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::collections::VecDeque;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    fn record(message: &str) -> LogData {
+        LogData::test(Level::Prod, "app", message)
+    }
+
+    pub(crate) struct Request {
+        pub(crate) headers: Vec<(String, String)>,
+        pub(crate) body: Vec<u8>,
+    }
+
+    impl Request {
+        pub(crate) fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Reads one request off a kept-alive connection, or `None` once it is closed.
+    fn read_request(reader: &mut BufReader<TcpStream>) -> Option<Request> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            match line.trim_end().split_once(": ") {
+                Some((name, value)) => headers.push((name.to_string(), value.to_string())),
+                None => break,
+            }
+        }
+        let mut request = Request { headers, body: Vec::new() };
+        let len: usize = request.header("Content-Length").and_then(|len| len.parse().ok()).unwrap_or(0);
+        request.body = vec![0; len];
+        reader.read_exact(&mut request.body).ok()?;
+        Some(request)
+    }
+
+    /// An endpoint answering with `statuses` in turn, then 200, and handing
+    /// over every request it got.
+    pub(crate) fn endpoint(statuses: &[u16]) -> (String, Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ingest", listener.local_addr().unwrap());
+        let statuses = Arc::new(Mutex::new(statuses.iter().copied().collect::<VecDeque<u16>>()));
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let statuses = Arc::clone(&statuses);
+                let requests = requests.clone();
+                thread::spawn(move || {
+                    let mut writer = stream.try_clone().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    while let Some(request) = read_request(&mut reader) {
+                        let status = statuses.lock().unwrap().pop_front().unwrap_or(200);
+                        let _ = requests.send(request);
+                        let response = format!("HTTP/1.1 {} Status\r\nContent-Length: 0\r\n\r\n", status);
+                        writer.write_all(response.as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        (url, received)
+    }
+
+    pub(crate) fn received(requests: &Receiver<Request>) -> Vec<Request> {
+        requests.try_iter().collect()
+    }
+
+    #[test]
+    fn batches_are_cut_at_the_record_limit_and_gzipped_with_headers() {
+        let (url, requests) = endpoint(&[]);
+        let output = HttpOutput::new(&url)
+            .encoding(BatchEncoding::Ndjson)
+            .gzip(true)
+            .header("Authorization", "Bearer token")
+            .max_records(2)
+            .max_delay(Duration::from_secs(60));
+        let batcher: Batcher = Mutex::new(None);
+        for message in ["a", "b", "c", "d", "e"] {
+            sender::enqueue(&batcher, &output, output.encoding.record(&record(message)));
+        }
+        sender::flush(&batcher, Instant::now() + Duration::from_secs(5));
+
+        let requests = received(&requests);
+        let batches: Vec<Vec<String>> = requests
+            .iter()
+            .map(|request| {
+                assert_eq!(request.header("Content-Type"), Some("application/x-ndjson"));
+                assert_eq!(request.header("Content-Encoding"), Some("gzip"));
+                assert_eq!(request.header("Authorization"), Some("Bearer token"));
+                let mut body = String::new();
+                GzDecoder::new(request.body.as_slice()).read_to_string(&mut body).unwrap();
+                body.lines()
+                    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(batches, [vec!["\"a\"", "\"b\""], vec!["\"c\"", "\"d\""], vec!["\"e\""]]);
+        let stats = batcher_stats(&batcher).unwrap();
+        assert_eq!((stats.sent_batches, stats.sent_records, stats.queued), (3, 5, 0));
+        sender::close(&batcher);
+    }
+
+    #[test]
+    fn batches_are_cut_at_the_byte_limit() {
+        let (url, requests) = endpoint(&[]);
+        let one = HttpOutput::new(&url).encoding.record(&record("x")).len();
+        let output = HttpOutput::new(&url).max_bytes(one * 2 + 1).max_delay(Duration::from_secs(60));
+        let batcher: Batcher = Mutex::new(None);
+        for _ in 0..5 {
+            sender::enqueue(&batcher, &output, output.encoding.record(&record("x")));
+        }
+        sender::flush(&batcher, Instant::now() + Duration::from_secs(5));
+
+        let sizes: Vec<usize> = received(&requests)
+            .iter()
+            .map(|request| serde_json::from_slice::<Vec<serde_json::Value>>(&request.body).unwrap().len())
+            .collect();
+        assert_eq!(sizes, [2, 2, 1]);
+        sender::close(&batcher);
+    }
+
+    #[test]
+    fn server_errors_are_retried_and_client_errors_are_not() {
+        let (url, requests) = endpoint(&[503, 500, 200, 400]);
+        let output = HttpOutput::new(&url).max_retries(3).max_delay(Duration::ZERO);
+        let batcher: Batcher = Mutex::new(None);
+
+        sender::enqueue(&batcher, &output, output.encoding.record(&record("retried")));
+        sender::flush(&batcher, Instant::now() + Duration::from_secs(5));
+        let stats = batcher_stats(&batcher).unwrap();
+        assert_eq!((stats.sent_batches, stats.failed_batches), (1, 0));
+        assert_eq!(stats.last_error.as_deref(), Some("HTTP 500 Status"));
+        assert_eq!(received(&requests).len(), 3);
+
+        sender::enqueue(&batcher, &output, output.encoding.record(&record("refused")));
+        sender::flush(&batcher, Instant::now() + Duration::from_secs(5));
+        assert_eq!(received(&requests).len(), 1);
+        let stats = batcher_stats(&batcher).unwrap();
+        assert_eq!((stats.sent_batches, stats.failed_batches, stats.dropped), (1, 1, 1));
+        assert_eq!(stats.last_status, Some(400));
+        sender::close(&batcher);
+    }
+
+    #[test]
+    fn shutting_down_sends_what_is_still_queued() {
+        let (url, requests) = endpoint(&[]);
+        let output = HttpOutput::new(&url).max_delay(Duration::from_secs(60));
+        let batcher: Batcher = Mutex::new(None);
+        sender::enqueue(&batcher, &output, output.encoding.record(&record("last")));
+        sender::shut_down(&batcher, Instant::now() + Duration::from_secs(5));
+        assert_eq!(received(&requests).len(), 1);
+        assert!(batcher.lock().unwrap().is_none());
+    }
+}
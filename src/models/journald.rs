@@ -0,0 +1,338 @@
+use crate::models::connection;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::syslog::default_app_name;
+use once_cell::sync::Lazy;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Where systemd-journald takes native protocol datagrams.
+pub const DEFAULT_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Field names journald accepts are at most this long.
+const MAX_FIELD_NAME_LEN: usize = 64;
+/// The fields [`payload`] writes itself, which key-value pairs must not repeat.
+const RECORD_FIELDS: &[&str] = &[
+    "MESSAGE",
+    "PRIORITY",
+    "SYSLOG_IDENTIFIER",
+    "CODE_FILE",
+    "CODE_LINE",
+    "CODE_MODULE",
+    "TID",
+    "THREAD_NAME",
+    "LOG_SOURCE",
+];
+
+static SOCKET: Lazy<io::Result<UnixDatagram>> = Lazy::new(|| {
+    let socket = UnixDatagram::unbound()?;
+    socket.set_write_timeout(Some(connection::WRITE_TIMEOUT))?;
+    Ok(socket)
+});
+/// Records journald did not take, including those it was too busy to take in time.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Sends records to the systemd journal as structured fields: `MESSAGE`,
+/// `PRIORITY`, `SYSLOG_IDENTIFIER`, `CODE_FILE`, `CODE_LINE`, `CODE_MODULE`,
+/// `TID`, `THREAD_NAME`, `LOG_SOURCE`, then every key-value pair under its key
+/// upper-cased, with characters journald refuses turned into `_`. Keys that
+/// would land on one of the fields before, such as `priority`, get a `KV_`
+/// prefix instead.
+///
+/// Priorities are those of the syslog output: `Prod` is 6, `Debug` and `Trace` are 7.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournaldOutput {
+    pub(crate) socket: PathBuf,
+    pub(crate) identifier: String,
+    pub(crate) max_level: LevelFilter,
+}
+
+impl JournaldOutput {
+    /// Sends to [`DEFAULT_SOCKET`] under the running executable's name.
+    pub fn new() -> JournaldOutput {
+        JournaldOutput {
+            socket: PathBuf::from(DEFAULT_SOCKET),
+            identifier: default_app_name(),
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    /// Sends to another socket, e.g. a test listener.
+    pub fn socket<P: Into<PathBuf>>(mut self, socket: P) -> JournaldOutput {
+        self.socket = socket.into();
+        self
+    }
+
+    /// The `SYSLOG_IDENTIFIER` records are sent under.
+    pub fn identifier(mut self, identifier: &str) -> JournaldOutput {
+        self.identifier = identifier.to_string();
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> JournaldOutput {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn socket_path(&self) -> &Path {
+        &self.socket
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+}
+
+impl Default for JournaldOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::Prod => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+/// Upper-case letters, digits and `_`, starting with a letter; fields that
+/// start with `_` are reserved for journald itself, and those in
+/// [`RECORD_FIELDS`] for the record.
+fn field_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            c @ ('A'..='Z' | '0'..='9') => c,
+            _ => '_',
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_uppercase()) || RECORD_FIELDS.contains(&name.as_str()) {
+        name.insert_str(0, "KV_");
+    }
+    name.truncate(MAX_FIELD_NAME_LEN);
+    name
+}
+
+/// `NAME=value\n`, or, for values holding a newline, the name, a newline,
+/// the value's length as a little-endian `u64`, the value and a newline.
+fn push_field(payload: &mut Vec<u8>, name: &str, value: &str) {
+    payload.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value.as_bytes());
+    payload.push(b'\n');
+}
+
+/// `record` as one native protocol datagram.
+pub fn payload(output: &JournaldOutput, record: &LogData) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(256 + record.message.len());
+    push_field(&mut payload, "MESSAGE", &record.message);
+    push_field(&mut payload, "PRIORITY", priority(record.level));
+    push_field(&mut payload, "SYSLOG_IDENTIFIER", &output.identifier);
+    if let Some(file) = &record.file {
+        push_field(&mut payload, "CODE_FILE", file);
+    }
+    if let Some(line) = record.line {
+        push_field(&mut payload, "CODE_LINE", &line.to_string());
+    }
+    if let Some(module_path) = &record.module_path {
+        push_field(&mut payload, "CODE_MODULE", module_path);
+    }
+    push_field(&mut payload, "TID", &record.thread.to_string());
+    if let Some(name) = &record.thread_name {
+        push_field(&mut payload, "THREAD_NAME", name);
+    }
+    push_field(&mut payload, "LOG_SOURCE", &record.source);
+    for (key, value) in &record.key_values {
+        push_field(&mut payload, &field_name(key), value);
+    }
+    payload
+}
+
+/// Datagrams too large for the socket go as a sealed memfd instead, passed
+/// along with `SCM_RIGHTS`; journald reads the record from the descriptor.
+fn send_memfd(socket: &Path, payload: &[u8]) -> io::Result<()> {
+    let fd = unsafe { libc::memfd_create(c"common_logger-journal".as_ptr(), libc::MFD_ALLOW_SEALING | libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(payload)?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let sender = UnixDatagram::unbound()?;
+    sender.set_write_timeout(Some(connection::WRITE_TIMEOUT))?;
+    sender.connect(socket)?;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(size_of::<libc::c_int>() as u32) } as usize];
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = control.len() as _;
+    unsafe {
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(size_of::<libc::c_int>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(header).cast::<libc::c_int>(), fd);
+    }
+    if unsafe { libc::sendmsg(sender.as_raw_fd(), &message, libc::MSG_NOSIGNAL) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sends `record` to the journal `output` describes. Records the journal
+/// cannot take within [`connection::WRITE_TIMEOUT`] are reported and dropped.
+pub(crate) fn send(output: &JournaldOutput, record: &LogData) {
+    if !output.accepts(record.level) {
+        return;
+    }
+    let payload = payload(output, record);
+    let sent = match SOCKET.as_ref() {
+        Ok(socket) => match socket.send_to(&payload, &output.socket) {
+            Err(e) if e.raw_os_error() == Some(libc::EMSGSIZE) => send_memfd(&output.socket, &payload),
+            sent => sent.map(drop),
+        },
+        Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+    };
+    if let Err(e) = sent {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        eprintln!("ERROR::SENDING TO JOURNALD {}: {}", output.socket.display(), e);
+    }
+}
+
+/// How many records the journald output has dropped.
+pub(crate) fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(message: &str, key_values: &[(&str, &str)]) -> LogData {
+        LogData {
+            thread: 7,
+            module_path: Some("app::db::pool".to_string()),
+            line: Some(12),
+            ..LogData::test(Level::Prod, "app::db", message).key_values(key_values)
+        }
+    }
+
+    /// Reads a native protocol datagram back into its fields, in order.
+    fn fields(mut datagram: &[u8]) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        while !datagram.is_empty() {
+            let end = datagram.iter().position(|&byte| byte == b'\n' || byte == b'=').unwrap();
+            let name = String::from_utf8(datagram[..end].to_vec()).unwrap();
+            let value = if datagram[end] == b'=' {
+                let len = datagram[end..].iter().position(|&byte| byte == b'\n').unwrap() - 1;
+                let value = &datagram[end + 1..end + 1 + len];
+                datagram = &datagram[end + 2 + len..];
+                value
+            } else {
+                let len = u64::from_le_bytes(datagram[end + 1..end + 9].try_into().unwrap()) as usize;
+                let value = &datagram[end + 9..end + 9 + len];
+                assert_eq!(datagram[end + 9 + len], b'\n');
+                datagram = &datagram[end + 10 + len..];
+                value
+            };
+            fields.push((name, String::from_utf8(value.to_vec()).unwrap()));
+        }
+        fields
+    }
+
+    fn values<'a>(fields: &'a [(String, String)], name: &str) -> Vec<&'a str> {
+        fields
+            .iter()
+            .filter(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn field_names_are_sanitized() {
+        assert_eq!(field_name("user.id"), "USER_ID");
+        assert_eq!(field_name("2fa"), "KV_2FA");
+        assert_eq!(field_name("_boot"), "KV__BOOT");
+        assert_eq!(field_name("Priority"), "KV_PRIORITY");
+        assert_eq!(field_name(&"k".repeat(100)).len(), MAX_FIELD_NAME_LEN);
+    }
+
+    #[test]
+    fn records_reach_a_local_socket_as_native_protocol_fields() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.sock");
+        let journal = UnixDatagram::bind(&path).unwrap();
+        let output = JournaldOutput::new().socket(&path).identifier("test-app");
+
+        let record = record(
+            "first line\nsecond line",
+            &[("priority", "3"), ("message", "other"), ("request-id", "r=1\n2")],
+        );
+        send(&output, &record);
+        let mut buf = vec![0u8; 64 * 1024];
+        let len = journal.recv(&mut buf).unwrap();
+        let fields = fields(&buf[..len]);
+
+        assert_eq!(values(&fields, "MESSAGE"), ["first line\nsecond line"]);
+        assert_eq!(values(&fields, "PRIORITY"), ["6"]);
+        assert_eq!(values(&fields, "SYSLOG_IDENTIFIER"), ["test-app"]);
+        assert_eq!(values(&fields, "CODE_LINE"), ["12"]);
+        assert_eq!(values(&fields, "CODE_MODULE"), ["app::db::pool"]);
+        assert_eq!(values(&fields, "TID"), ["7"]);
+        assert_eq!(values(&fields, "LOG_SOURCE"), ["app::db"]);
+        assert_eq!(values(&fields, "KV_PRIORITY"), ["3"]);
+        assert_eq!(values(&fields, "KV_MESSAGE"), ["other"]);
+        assert_eq!(values(&fields, "REQUEST_ID"), ["r=1\n2"]);
+        assert!(values(&fields, "CODE_FILE").is_empty());
+    }
+
+    #[test]
+    fn records_below_max_level_are_not_sent() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.sock");
+        let journal = UnixDatagram::bind(&path).unwrap();
+        journal.set_nonblocking(true).unwrap();
+        let output = JournaldOutput::new().socket(&path).max_level(LevelFilter::Prod);
+
+        let mut debug = record("noise", &[]);
+        debug.level = Level::Debug;
+        send(&output, &debug);
+        let mut buf = [0u8; 1024];
+        assert_eq!(journal.recv(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn records_a_full_journal_cannot_take_in_time_are_dropped() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.sock");
+        let _journal = UnixDatagram::bind(&path).unwrap();
+        let output = JournaldOutput::new().socket(&path);
+
+        let before = dropped();
+        let started = std::time::Instant::now();
+        for _ in 0..100_000 {
+            send(&output, &record("nobody reads this", &[]));
+            if dropped() > before {
+                break;
+            }
+        }
+        assert_eq!(dropped(), before + 1);
+        assert!(started.elapsed() >= connection::WRITE_TIMEOUT);
+    }
+}
@@ -15,7 +15,7 @@ use std::str::FromStr;
 #[repr(usize)]
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub enum Level {
-    Prod,
+    Prod = 1,
     Debug,
     Trace,
 }
@@ -50,10 +50,7 @@ impl FromStr for Level {
             LOG_LEVEL_NAMES
                 .iter()
                 .position(|&name| name.eq_ignore_ascii_case(level))
-                .into_iter()
-                .filter(|&idx| idx != 0)
-                .map(|idx| Level::from_usize(idx).unwrap())
-                .next(),
+                .and_then(Level::from_usize),
             ParseLevelError(()),
         )
     }
@@ -82,7 +79,11 @@ impl Level {
 
     #[inline]
     pub fn to_level_filter(&self) -> LevelFilter {
-        LevelFilter::from_usize(*self as usize).unwrap()
+        match self {
+            Level::Prod => LevelFilter::Prod,
+            Level::Debug => LevelFilter::Debug,
+            Level::Trace => LevelFilter::Trace,
+        }
     }
 
     pub fn as_str(&self) -> &'static str {
@@ -90,7 +91,7 @@ impl Level {
     }
 
     pub fn iter() -> impl Iterator<Item = Self> {
-        (1..4).map(|i| Self::from_usize(i).unwrap())
+        [Level::Prod, Level::Debug, Level::Trace].into_iter()
     }
 }
 
@@ -115,7 +116,7 @@ impl FromStr for LevelFilter {
             LOG_LEVEL_NAMES
                 .iter()
                 .position(|&name| name.eq_ignore_ascii_case(level))
-                .map(|p| LevelFilter::from_usize(p).unwrap()),
+                .and_then(LevelFilter::from_usize),
             ParseLevelError(()),
         )
     }
@@ -128,12 +129,12 @@ impl fmt::Display for LevelFilter {
 }
 
 impl LevelFilter {
-    fn from_usize(u: usize) -> Option<LevelFilter> {
+    pub fn from_usize(u: usize) -> Option<LevelFilter> {
         match u {
             0 => Some(LevelFilter::Off),
-            3 => Some(LevelFilter::Prod),
-            4 => Some(LevelFilter::Debug),
-            5 => Some(LevelFilter::Trace),
+            1 => Some(LevelFilter::Prod),
+            2 => Some(LevelFilter::Debug),
+            3 => Some(LevelFilter::Trace),
             _ => None,
         }
     }
@@ -152,6 +153,12 @@ impl LevelFilter {
         LOG_LEVEL_NAMES[*self as usize]
     }
     pub fn iter() -> impl Iterator<Item = Self> {
-        (0..4).map(|i| Self::from_usize(i).unwrap())
+        [
+            LevelFilter::Off,
+            LevelFilter::Prod,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ]
+        .into_iter()
     }
 }
@@ -0,0 +1,196 @@
+use crate::models::error::LoggerError;
+use crate::models::format::level_name;
+use crate::models::level::Level;
+use crate::models::logger::LogData;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write;
+
+/// Keys the logger writes itself; key-value pairs follow them as top-level keys.
+const TIME_KEY: &str = "ts";
+const LEVEL_KEY: &str = "level";
+const SOURCE_KEY: &str = "source";
+const MESSAGE_KEY: &str = "msg";
+const THREAD_ID_KEY: &str = "thread_id";
+const THREAD_NAME_KEY: &str = "thread_name";
+const MODULE_PATH_KEY: &str = "module_path";
+const FILE_KEY: &str = "file";
+const LINE_KEY: &str = "line";
+const SEQUENCE_KEY: &str = "sequence";
+
+/// Keys cannot hold spaces, `=` or quotes; those become `_`.
+fn push_key(line: &mut String, key: &str) {
+    if key.is_empty() {
+        line.push('_');
+    }
+    for c in key.chars() {
+        line.push(if c <= ' ' || c == '=' || c == '"' || c == '\u{7f}' { '_' } else { c });
+    }
+}
+
+/// Values are quoted when they are empty or hold anything a reader would
+/// split on; inside quotes, `"`, `\` and control characters are escaped.
+fn push_value(line: &mut String, value: &str) {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\' || c.is_control());
+    if !needs_quotes {
+        line.push_str(value);
+        return;
+    }
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(line, "\\u{:04x}", c as u32);
+            }
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+fn push_pair(line: &mut String, key: &str, value: &str) {
+    if !line.is_empty() {
+        line.push(' ');
+    }
+    push_key(line, key);
+    line.push('=');
+    push_value(line, value);
+}
+
+/// `record` as one logfmt line, without the newline.
+pub fn render(record: &LogData) -> String {
+    let mut line = String::with_capacity(128 + record.message.len());
+    push_pair(&mut line, TIME_KEY, &record.time.to_rfc3339_opts(SecondsFormat::Micros, true));
+    push_pair(&mut line, LEVEL_KEY, level_name(record.level));
+    push_pair(&mut line, SOURCE_KEY, &record.source);
+    push_pair(&mut line, MESSAGE_KEY, &record.message);
+    push_pair(&mut line, THREAD_ID_KEY, &record.thread.to_string());
+    if let Some(name) = &record.thread_name {
+        push_pair(&mut line, THREAD_NAME_KEY, name);
+    }
+    if let Some(module_path) = &record.module_path {
+        push_pair(&mut line, MODULE_PATH_KEY, module_path);
+    }
+    if let Some(file) = &record.file {
+        push_pair(&mut line, FILE_KEY, file);
+    }
+    if let Some(number) = record.line {
+        push_pair(&mut line, LINE_KEY, &number.to_string());
+    }
+    push_pair(&mut line, SEQUENCE_KEY, &record.sequence.to_string());
+    for (key, value) in &record.key_values {
+        push_pair(&mut line, key, value);
+    }
+    line
+}
+
+fn invalid(line: &str) -> LoggerError {
+    LoggerError::InvalidData(format!("not a logfmt line: {}", line))
+}
+
+/// Splits a logfmt line into its key-value pairs, undoing the quoting.
+/// A key without `=` gets an empty value, as logfmt allows.
+pub fn parse_pairs(line: &str) -> Result<Vec<(String, String)>, LoggerError> {
+    let mut pairs = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ' ' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if key.is_empty() {
+            return Err(invalid(line));
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next().ok_or_else(|| invalid(line))? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(|| invalid(line))? {
+                            'n' => value.push('\n'),
+                            'r' => value.push('\r'),
+                            't' => value.push('\t'),
+                            'u' => {
+                                let hex: String = chars.by_ref().take(4).collect();
+                                let c = u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(char::from_u32)
+                                    .ok_or_else(|| invalid(line))?;
+                                value.push(c);
+                            }
+                            c => value.push(c),
+                        },
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|&c| c != ' ') {
+                    value.push(c);
+                }
+            }
+        }
+        pairs.push((key, value));
+        while chars.next_if_eq(&' ').is_some() {}
+    }
+    Ok(pairs)
+}
+
+/// Reads a line [`render`] wrote back into a record. Keys the logger does not
+/// write itself come back as key-value pairs.
+pub fn parse_record(line: &str) -> Result<LogData, LoggerError> {
+    let mut record = LogData {
+        message: String::new(),
+        level: Level::Prod,
+        source: String::new(),
+        time: DateTime::<Utc>::UNIX_EPOCH,
+        sequence: 0,
+        thread: 0,
+        thread_name: None,
+        module_path: None,
+        file: None,
+        line: None,
+        key_values: Vec::new(),
+    };
+    let mut has_time = false;
+    for (key, value) in parse_pairs(line)? {
+        match key.as_str() {
+            TIME_KEY => {
+                record.time = DateTime::parse_from_rfc3339(&value)
+                    .map_err(|_| invalid(line))?
+                    .with_timezone(&Utc);
+                has_time = true;
+            }
+            LEVEL_KEY => record.level = value.parse().map_err(|_| invalid(line))?,
+            SOURCE_KEY => record.source = value,
+            MESSAGE_KEY => record.message = value,
+            THREAD_ID_KEY => record.thread = value.parse().map_err(|_| invalid(line))?,
+            THREAD_NAME_KEY => record.thread_name = Some(value),
+            MODULE_PATH_KEY => record.module_path = Some(value),
+            FILE_KEY => record.file = Some(value),
+            LINE_KEY => record.line = Some(value.parse().map_err(|_| invalid(line))?),
+            SEQUENCE_KEY => record.sequence = value.parse().map_err(|_| invalid(line))?,
+            _ => record.key_values.push((key, value)),
+        }
+    }
+    if !has_time {
+        return Err(invalid(line));
+    }
+    Ok(record)
+}
+
+/// Whether `text`, the start of a file, looks like logfmt the logger wrote.
+pub fn is_logfmt(text: &str) -> bool {
+    text.starts_with("ts=")
+}
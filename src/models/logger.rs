@@ -5,116 +5,346 @@
     unused_qualifications
 )]
 
-use crate::models::constants::{DATE_FORMAT, NUM_LOGGING_THREADS, TIME_FORMAT};
+use crate::models::config::{config, LoggerConfig};
+use crate::models::constants::{LOGGING_BATCH_SIZE, LOGGING_THREAD_TIMEOUT, NUM_LOGGING_THREADS};
+use crate::models::error::LoggerError;
+use crate::models::flight_recorder;
+use crate::models::format::{self, LogFormat};
+use crate::models::fluentd;
+use crate::models::gelf;
+use crate::models::http;
+use crate::models::journald;
 use crate::models::level::Level;
 use crate::models::log::Log;
+use crate::models::loki;
 use crate::models::metadata::LogInfo;
+use crate::models::network;
 use crate::models::record::LogRecord;
+use crate::models::remote;
+use crate::models::retention;
+use crate::models::rotation;
+use crate::models::spool;
+use crate::models::syslog;
 use crate::models::threads::{thread_id, ThreadPool};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use crossbeam_queue::SegQueue;
-use std::ffi::CString;
-use std::fs::OpenOptions;
-use std::io::{BufWriter, Write};
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::log_console;lazy_static::lazy_static! {
-    static ref THREAD_POOL: ThreadPool = ThreadPool::new(NUM_LOGGING_THREADS);
+    static ref THREAD_POOL: Mutex<Option<ThreadPool>> = Mutex::new(None);
     static ref MAX_LOG_LEVEL_FILTER: AtomicUsize = AtomicUsize::new(Level::Trace as usize);
     static ref LOG_QUEUE :  Arc<Mutex<SegQueue<LogData>>> = Arc::new(Mutex::new(SegQueue::new()));
 }
 
-#[derive(Clone)]
-struct LogData {
-    args: String,
-    level: Level,
-    source: String,
+/// How long `flush` waits for each output with a sender thread of its own.
+const OUTPUT_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Records queued or being written; `flush` waits for this to reach zero.
+static PENDING_RECORDS: AtomicUsize = AtomicUsize::new(0);
+/// Numbers records in the order they were logged, across all threads.
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// A record as it travels from the logging thread to the outputs, with
+/// everything captured at the call site.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogData {
+    pub message: String,
+    pub level: Level,
+    pub source: String,
+    pub time: DateTime<Utc>,
+    pub sequence: u64,
+    pub thread: u64,
+    pub thread_name: Option<String>,
+    pub module_path: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub key_values: Vec<(String, String)>,
+}
+
+impl LogData {
+    fn capture(record: &LogRecord) -> LogData {
+        let current = thread::current();
+        LogData {
+            message: record.args.clone(),
+            level: record.metadata.level,
+            source: record.metadata.source.to_string(),
+            time: Utc::now(),
+            sequence: NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed),
+            thread: thread_id().unwrap_or_default(),
+            thread_name: current.name().map(str::to_string),
+            module_path: record.module_path.as_ref().map(|path| path.get().to_string()),
+            file: record.file.as_ref().map(|file| file.get().to_string()),
+            line: record.line,
+            key_values: record.kvs.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl LogData {
+    /// A record for output tests: thread 1, no call site and no key-value
+    /// pairs, logged at 2024-03-05 07:08:09 UTC. Other fields are set with
+    /// struct update syntax.
+    pub(crate) fn test(level: Level, source: &str, message: &str) -> LogData {
+        use chrono::TimeZone;
+        LogData {
+            message: message.to_string(),
+            level,
+            source: source.to_string(),
+            time: Utc.with_ymd_and_hms(2024, 3, 5, 7, 8, 9).unwrap(),
+            sequence: 0,
+            thread: 1,
+            thread_name: None,
+            module_path: None,
+            file: None,
+            line: None,
+            key_values: Vec::new(),
+        }
+    }
+
+    pub(crate) fn key_values(mut self, key_values: &[(&str, &str)]) -> LogData {
+        self.key_values = key_values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        self
+    }
 }
 
 pub(crate) struct AsyncLogger;
 
 impl<'x> Log for AsyncLogger {
     fn enabled(&self, metadata: &LogInfo) -> bool {
-        metadata.level <= Level::from_usize(MAX_LOG_LEVEL_FILTER.load(Ordering::Relaxed)+1).unwrap()
+        queues(metadata.level)
+            || config()
+                .flight_recorder
+                .as_ref()
+                .is_some_and(|recorder| recorder.accepts(metadata.level))
     }
 
     fn log(&self, record: &LogRecord) {
-        let log_data = LogData {
-            args: record.args.clone(),
-            level: record.metadata.level,
-            source: record.metadata.source.to_string(),
-        };
-        if self.enabled(&record.metadata) {
-            LOG_QUEUE.lock().unwrap().push(log_data.clone());
+        let config = config();
+        let level = record.metadata.level;
+        if let Some(recorder) = config.flight_recorder.as_ref().filter(|recorder| recorder.accepts(level)) {
+            // Written on the calling thread, so a crash cannot lose what is still queued.
+            let thread = thread_id().unwrap_or_default();
+            flight_recorder::record(recorder, level, thread, record.metadata.source, &record.args);
+        }
+        if queues(level) {
+            enqueue(LogData::capture(record), &config);
         }
 
     }
 
+    /// Waits, up to `LOGGING_THREAD_TIMEOUT`, for every queued record to be
+    /// written, then flushes all open files. Outputs with a sender thread of
+    /// their own are then given `OUTPUT_FLUSH_TIMEOUT` each to send theirs.
     fn flush(&self) {
-        // TODO: Implement logic to flush logs, ensuring all logs are processed
+        drain();
+        network::flush(output_deadline());
+        http::flush(output_deadline());
+        fluentd::flush(output_deadline());
+        loki::flush(output_deadline());
+        remote::flush(output_deadline());
     }
 }
 
+/// Waits, up to `LOGGING_THREAD_TIMEOUT`, for every queued record to be
+/// written, then flushes all open files.
+fn drain() {
+    let deadline = Instant::now() + Duration::from_secs(LOGGING_THREAD_TIMEOUT);
+    while PENDING_RECORDS.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    if let Err(e) = rotation::flush_all(false) {
+        eprintln!("ERROR::FLUSHING BUFFER: {}", e);
+    }
+}
+
+fn output_deadline() -> Instant {
+    Instant::now() + OUTPUT_FLUSH_TIMEOUT
+}
+
+/// Like `flush`, but stops the sender threads: each sends what it still
+/// holds within its `OUTPUT_FLUSH_TIMEOUT`, then ends.
+pub(crate) fn shut_down() {
+    drain();
+    network::shut_down(output_deadline());
+    http::shut_down(output_deadline());
+    fluentd::shut_down(output_deadline());
+    loki::shut_down(output_deadline());
+    remote::shut_down(output_deadline());
+}
+
 impl AsyncLogger {
     pub fn new() -> Self {
         AsyncLogger
     }
 
-    pub fn start_background_task() {
+    pub fn start_background_task() -> Result<(), LoggerError> {
+        let mut pool = THREAD_POOL.lock()?;
+        if pool.is_some() {
+            return Ok(());
+        }
+        let new_pool = ThreadPool::new(NUM_LOGGING_THREADS)?;
+        rotation::start_scheduler()?;
+        retention::request();
+        // Whatever a crashed run left in its spool is written before anything new.
+        let recovered = spool::recover(&config().directory)?;
+        PENDING_RECORDS.fetch_add(recovered, Ordering::SeqCst);
+
         for _ in 0..NUM_LOGGING_THREADS {
             let log_queue = Arc::clone(&LOG_QUEUE);
-            THREAD_POOL.execute(move || loop {
-                if let Some(log_data) = log_queue.lock().unwrap().pop() {
-                    let current_thread = thread_id();
-                    log_console!(&log_data.source.clone(), log_data.level.clone(), current_thread.clone(), log_data.args.clone());
-                    AsyncLogger::write_log_to_file(&log_data, current_thread);
+            new_pool.execute(move || loop {
+                let mut batch = 0;
+                while batch < LOGGING_BATCH_SIZE {
+                    let next = log_queue
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .pop();
+                    let Some(log_data) = next else {
+                        break;
+                    };
+                    // A failing record must never take the worker down with it.
+                    let processed = panic::catch_unwind(AssertUnwindSafe(|| {
+                        AsyncLogger::write_log_to_console(&log_data);
+                        AsyncLogger::write_log_to_file(&log_data);
+                        AsyncLogger::write_log_to_outputs(&log_data);
+                    }));
+                    if let Err(payload) = processed {
+                        eprintln!("{}", LoggerError::from_panic(payload));
+                    }
+                    batch += 1;
+                }
+
+                let threshold = config().spool_threshold.unwrap_or(usize::MAX);
+                if let Err(e) = spool::replay(threshold, &log_queue) {
+                    eprintln!("ERROR::REPLAYING SPOOL: {}", e);
                 }
-                thread::sleep(std::time::Duration::from_millis(100));
-            });
+                if batch == 0 {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                if let Err(e) = rotation::flush_batch() {
+                    eprintln!("ERROR::FLUSHING BUFFER: {}", e);
+                }
+                PENDING_RECORDS.fetch_sub(batch, Ordering::SeqCst);
+            })?;
         }
+
+        *pool = Some(new_pool);
+        Ok(())
     }
 
     #[inline]
-    fn write_log_to_file(log_record: &LogData, current_thread: u64) {
-        let current_date = Utc::now();
-        let formatted_date = current_date.format(DATE_FORMAT).to_string();
-        let file_path_str = format!("{}_{:?}.log", formatted_date, log_record.level);
-
-        if let Ok(file_path_cstr) = CString::new(file_path_str.clone()) {
-            let file_path = file_path_cstr.to_string_lossy().to_string();
-            if let Ok(file) = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(file_path)
-            {
-                let mut buf_writer = BufWriter::new(file);
-                let log_entry = format!(
-                    "[{:?}] - [{:<60}] - [{:<30}] - [{:<19}] - [{}]",
-                    log_record.level,
-                    log_record.source.trim(),
-                    log_record.args.trim(),
-                    formatted_date.trim(),
-                    current_thread
-                );
-
-                if let Err(e) = writeln!(&mut buf_writer, "{}", log_entry) {
-                    eprintln!("ERROR::FILE DIDN'T OPEN: {}", e);
+    fn write_log_to_console(log_record: &LogData) {
+        let config = config();
+        let template = config.console_template.as_ref();
+        match config.console_format {
+            LogFormat::Text if template.is_none() => {
+                log_console!(&log_record.source.clone(), log_record.level, log_record.thread, log_record.message.clone())
+            }
+            format => {
+                if let Some(line) = format::line(format, template, log_record, config.timezone, &config.service) {
+                    println!("{}", line);
                 }
-                if let Err(e) = buf_writer.flush() {
-                    eprintln!("ERROR::FLUSHING BUFFER: {}", e);
+            }
+        }
+    }
+
+    /// Hands the record to every output besides the console and the files.
+    #[inline]
+    fn write_log_to_outputs(log_record: &LogData) {
+        let config = config();
+        if let Some(output) = &config.syslog {
+            syslog::send(output, log_record, config.timezone);
+        }
+        if let Some(output) = &config.journald {
+            journald::send(output, log_record);
+        }
+        if let Some(output) = &config.network {
+            network::send(output, log_record);
+        }
+        if let Some(output) = &config.http {
+            http::send(output, log_record);
+        }
+        if let Some(output) = &config.gelf {
+            gelf::send(output, log_record);
+        }
+        if let Some(output) = &config.fluentd {
+            fluentd::send(output, log_record);
+        }
+        if let Some(output) = &config.loki {
+            loki::send(output, log_record);
+        }
+        if let Some(output) = &config.remote {
+            remote::send(output, log_record);
+        }
+    }
+
+    #[inline]
+    fn write_log_to_file(log_record: &LogData) {
+        let config = config();
+        let current_date = config.timezone.now();
+
+        let mut files = Vec::new();
+        for route in &config.routes {
+            for file in route.files_for(
+                &config.file_name_pattern,
+                &config.directory,
+                &current_date,
+                log_record.level,
+                &log_record.source,
+            ) {
+                // Overlapping routes must not write the same record twice.
+                if !files.iter().any(|(path, _)| *path == file) {
+                    files.push((file, route));
                 }
-            } else {
-                eprintln!("ERROR::FILE DIDN'T OPEN: {}", file_path_str);
             }
-        } else {
-            eprintln!("ERROR::CREATING CSTRING FROM FILE PATH: {}", file_path_str);
         }
+
+        for (file_path, route) in files {
+            let written = rotation::append(&file_path, route, log_record, &config, &current_date);
+            if let Err(e) = written {
+                eprintln!("ERROR::WRITING LOG FILE {}: {}", file_path.display(), e);
+            }
+        }
+    }
+}
+
+fn enqueue(log_data: LogData, config: &LoggerConfig) {
+    PENDING_RECORDS.fetch_add(1, Ordering::SeqCst);
+    match config.spool_threshold {
+        Some(threshold) => spool::push(log_data, threshold, &config.directory, &LOG_QUEUE),
+        None => LOG_QUEUE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(log_data),
+    }
+}
+
+/// Queues a record captured elsewhere, e.g. in a remote output's client,
+/// for the workers to write like one logged here.
+pub(crate) fn submit(log_data: LogData) {
+    if queues(log_data.level) {
+        enqueue(log_data, &config());
     }
 }
 
+/// Records logged but not yet handed to every output.
+pub(crate) fn pending_records() -> usize {
+    PENDING_RECORDS.load(Ordering::SeqCst)
+}
+
+/// Whether records at `level` pass the log level and go to the files and console.
+fn queues(level: Level) -> bool {
+    level as usize <= MAX_LOG_LEVEL_FILTER.load(Ordering::Relaxed)
+}
+
 pub fn set_max_log_level(level: Level) {
     MAX_LOG_LEVEL_FILTER.store(level as usize, Ordering::Relaxed);
 }
@@ -0,0 +1,406 @@
+use crate::models::error::LoggerError;
+use crate::models::format::{json_line, level_name};
+use crate::models::http::{self, HttpOutput, HttpStats};
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::sender::{self, Batching, Sender};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where Loki takes pushed streams, added to URLs that do not end in it already.
+pub const PUSH_PATH: &str = "/loki/api/v1/push";
+/// Labels for streams when no others are chosen.
+pub const DEFAULT_LABEL_FIELDS: &[LabelField] = &[LabelField::Level, LabelField::Source];
+
+lazy_static::lazy_static! {
+    static ref BATCHER: sender::Slot<LokiOutput> = Mutex::new(None);
+}
+
+/// A record field a stream can be labeled by, under the field's own name.
+///
+/// Every distinct set of label values is a stream of its own in Loki, so
+/// only fields with few values are offered; the message never is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LabelField {
+    /// `level`, lowercase, as the JSON format writes it.
+    Level,
+    /// `source`, as passed to the logging macros.
+    Source,
+    /// `thread_name`, left out for unnamed threads.
+    ThreadName,
+    /// `module_path`, left out when unknown.
+    ModulePath,
+    /// `file`, left out when unknown.
+    File,
+}
+
+impl LabelField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LabelField::Level => "level",
+            LabelField::Source => "source",
+            LabelField::ThreadName => "thread_name",
+            LabelField::ModulePath => "module_path",
+            LabelField::File => "file",
+        }
+    }
+
+    fn value<'a>(&self, record: &'a LogData) -> Option<&'a str> {
+        match self {
+            LabelField::Level => Some(level_name(record.level)),
+            LabelField::Source => Some(&record.source),
+            LabelField::ThreadName => record.thread_name.as_deref(),
+            LabelField::ModulePath => record.module_path.as_deref(),
+            LabelField::File => record.file.as_deref(),
+        }
+    }
+}
+
+impl FromStr for LabelField {
+    type Err = LoggerError;
+
+    fn from_str(field: &str) -> Result<LabelField, Self::Err> {
+        match field.to_ascii_lowercase().replace('-', "_").as_str() {
+            "level" => Ok(LabelField::Level),
+            "source" => Ok(LabelField::Source),
+            "thread_name" | "thread" => Ok(LabelField::ThreadName),
+            "module_path" | "module" => Ok(LabelField::ModulePath),
+            "file" => Ok(LabelField::File),
+            _ => Err(LoggerError::InvalidConfig(format!("`{}` cannot be a Loki label", field))),
+        }
+    }
+}
+
+/// Loki's rule for label names, that of Prometheus: `[a-zA-Z_][a-zA-Z0-9_]*`.
+fn is_label_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    bytes.next().is_some_and(|first| first.is_ascii_alphabetic() || first == b'_')
+        && bytes.all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+/// A record as it is queued for Loki: the labels of its stream, when it
+/// was logged, and its line.
+pub(crate) struct Entry {
+    labels: BTreeMap<String, String>,
+    nanos: i64,
+    line: String,
+}
+
+#[derive(Serialize)]
+struct Stream {
+    stream: BTreeMap<String, String>,
+    values: Vec<(String, String)>,
+}
+
+#[derive(Serialize)]
+struct PushRequest {
+    streams: Vec<Stream>,
+}
+
+/// A push request body: entries with the same labels are merged into one
+/// stream and sorted by when they were logged, which the workers need not
+/// have kept.
+fn push_request(entries: impl IntoIterator<Item = Entry>) -> Vec<u8> {
+    let mut merged: BTreeMap<BTreeMap<String, String>, Vec<(i64, String)>> = BTreeMap::new();
+    for entry in entries {
+        merged.entry(entry.labels).or_default().push((entry.nanos, entry.line));
+    }
+    let streams = merged
+        .into_iter()
+        .map(|(stream, mut values)| {
+            values.sort_by_key(|(nanos, _)| *nanos);
+            let values = values.into_iter().map(|(nanos, line)| (nanos.to_string(), line)).collect();
+            Stream { stream, values }
+        })
+        .collect();
+    // Maps of strings always serialize.
+    serde_json::to_vec(&PushRequest { streams }).unwrap_or_default()
+}
+
+/// Pushes records to Grafana Loki's `/loki/api/v1/push` JSON API, batched
+/// by the HTTP output's machinery: sent once `max_records` records or
+/// `max_bytes` are queued, or the oldest has waited `max_delay`, with the
+/// same retries.
+///
+/// Streams are labeled by `label_fields`, `level` and `source` unless
+/// chosen otherwise, and by any fixed labels; each entry's line is the
+/// record in the `Json` file format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LokiOutput {
+    pub(crate) http: HttpOutput,
+    pub(crate) label_fields: Vec<LabelField>,
+    pub(crate) labels: Vec<(String, String)>,
+}
+
+impl LokiOutput {
+    /// Pushes to the Loki at `url`, e.g. `http://localhost:3100`; the push
+    /// path is added unless `url` ends in it.
+    pub fn new(url: &str) -> LokiOutput {
+        let url = url.trim_end_matches('/');
+        let url = match url.ends_with(PUSH_PATH) {
+            true => url.to_string(),
+            false => format!("{}{}", url, PUSH_PATH),
+        };
+        LokiOutput {
+            http: HttpOutput::new(&url),
+            label_fields: DEFAULT_LABEL_FIELDS.to_vec(),
+            labels: Vec::new(),
+        }
+    }
+
+    /// The record fields streams are labeled by, replacing the default ones.
+    pub fn label_fields(mut self, fields: &[LabelField]) -> LokiOutput {
+        self.label_fields = fields.to_vec();
+        self
+    }
+
+    /// Labels every stream `name="value"`, e.g. `job` or `env`.
+    pub fn label(mut self, name: &str, value: &str) -> LokiOutput {
+        self.labels.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Pushes as `tenant` of a multi-tenant Loki, in `X-Scope-OrgID`.
+    pub fn tenant(mut self, tenant: &str) -> LokiOutput {
+        self.http = self.http.header("X-Scope-OrgID", tenant);
+        self
+    }
+
+    /// Sends `name: value` with every request, e.g. an `Authorization` token.
+    pub fn header(mut self, name: &str, value: &str) -> LokiOutput {
+        self.http = self.http.header(name, value);
+        self
+    }
+
+    /// Compresses request bodies, sent with `Content-Encoding: gzip`.
+    pub fn gzip(mut self, gzip: bool) -> LokiOutput {
+        self.http = self.http.gzip(gzip);
+        self
+    }
+
+    pub fn max_records(mut self, max_records: usize) -> LokiOutput {
+        self.http = self.http.max_records(max_records);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> LokiOutput {
+        self.http = self.http.max_bytes(max_bytes);
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> LokiOutput {
+        self.http = self.http.max_delay(max_delay);
+        self
+    }
+
+    /// Records kept while batches cannot be sent before the oldest are dropped.
+    pub fn queue_capacity(mut self, capacity: usize) -> LokiOutput {
+        self.http = self.http.queue_capacity(capacity);
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> LokiOutput {
+        self.http = self.http.max_level(max_level);
+        self
+    }
+
+    #[inline]
+    pub fn url(&self) -> &str {
+        self.http.url()
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        self.http.accepts(level)
+    }
+
+    /// `record` as it is queued: labeled by `label_fields` and `labels`,
+    /// its line the `Json` file format's, at the time it was logged.
+    pub(crate) fn entry(&self, record: &LogData) -> Entry {
+        let mut labels: BTreeMap<String, String> = self.labels.iter().cloned().collect();
+        for field in &self.label_fields {
+            if let Some(value) = field.value(record) {
+                labels.insert(field.name().to_string(), value.to_string());
+            }
+        }
+        Entry {
+            labels,
+            nanos: record.time.timestamp_nanos_opt().unwrap_or_default(),
+            line: json_line(record),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), LoggerError> {
+        self.http.validate()?;
+        // Loki refuses streams without labels; these two are always there.
+        let labeled = self
+            .label_fields
+            .iter()
+            .any(|field| matches!(field, LabelField::Level | LabelField::Source));
+        if !labeled && self.labels.is_empty() {
+            return Err(LoggerError::InvalidConfig(
+                "Loki streams need a fixed label, or the level or source one".to_string(),
+            ));
+        }
+        for (name, _) in &self.labels {
+            if !is_label_name(name) {
+                return Err(LoggerError::InvalidConfig(format!("`{}` is not a Loki label name", name)));
+            }
+            if self.label_fields.iter().any(|field| field.name() == name) {
+                return Err(LoggerError::InvalidConfig(format!("Loki label `{}` is also a label field", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl sender::Output for LokiOutput {
+    type Record = Entry;
+    const KIND: &'static str = "loki";
+
+    fn destination(&self) -> String {
+        self.http.destination()
+    }
+
+    fn queue_capacity(&self) -> usize {
+        self.http.queue_capacity
+    }
+
+    fn batching(&self) -> Batching {
+        self.http.batching()
+    }
+
+    /// About what `entry` adds to the request body.
+    fn record_len(entry: &Entry) -> usize {
+        let labels: usize = entry.labels.iter().map(|(name, value)| name.len() + value.len()).sum();
+        labels + entry.line.len() + 32
+    }
+
+    fn run(sender: Arc<Sender<LokiOutput>>) {
+        let agent = http::agent(&sender.output.http);
+        while let Some(batch) = sender.next_batch() {
+            let records = batch.len();
+            let body = push_request(batch.into_iter().map(|(_, entry)| entry));
+            http::post_batch(&sender, &sender.output.http, &agent, body, records);
+        }
+    }
+}
+
+/// Queues `record` for the next push; never waits for Loki.
+pub(crate) fn send(output: &LokiOutput, record: &LogData) {
+    if output.accepts(record.level) {
+        sender::enqueue(&BATCHER, output, output.entry(record));
+    }
+}
+
+/// Pushes every pending record now, waiting until `deadline` for the batches to go out.
+pub(crate) fn flush(deadline: Instant) {
+    sender::flush(&BATCHER, deadline);
+}
+
+/// Stops the sender thread once it has pushed what it still holds.
+pub(crate) fn close() {
+    sender::close(&BATCHER);
+}
+
+/// Stops the sender thread, waiting until `deadline` for what it still holds to go out.
+pub(crate) fn shut_down(deadline: Instant) {
+    sender::shut_down(&BATCHER, deadline);
+}
+
+/// The current sender's numbers, if a Loki output has been used.
+pub(crate) fn stats() -> Option<HttpStats> {
+    http::batcher_stats(&BATCHER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::http::tests::{endpoint, received};
+    use chrono::{TimeZone, Utc};
+    use serde_json::Value;
+
+    fn record(level: Level, source: &str, message: &str, second: u32) -> LogData {
+        LogData {
+            time: Utc.with_ymd_and_hms(2024, 3, 5, 7, 8, second).unwrap(),
+            ..LogData::test(level, source, message)
+        }
+    }
+
+    #[test]
+    fn urls_end_in_the_push_path() {
+        assert_eq!(LokiOutput::new("http://loki:3100/").url(), "http://loki:3100/loki/api/v1/push");
+        assert_eq!(LokiOutput::new("http://loki:3100/loki/api/v1/push").url(), "http://loki:3100/loki/api/v1/push");
+    }
+
+    #[test]
+    fn pushes_one_sorted_stream_per_label_set() {
+        let (url, requests) = endpoint(&[]);
+        let output = LokiOutput::new(&url)
+            .label("job", "test")
+            .tenant("team-a")
+            .max_delay(Duration::from_secs(60));
+        let batcher: sender::Slot<LokiOutput> = Mutex::new(None);
+        // Workers can hand records over out of order.
+        for (level, source, message, second) in [
+            (Level::Prod, "app::db", "third", 3),
+            (Level::Debug, "app::http", "request", 2),
+            (Level::Prod, "app::db", "first", 1),
+            (Level::Prod, "app::db", "second", 2),
+        ] {
+            sender::enqueue(&batcher, &output, output.entry(&record(level, source, message, second)));
+        }
+        sender::flush(&batcher, Instant::now() + Duration::from_secs(5));
+
+        let requests = received(&requests);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("X-Scope-OrgID"), Some("team-a"));
+        assert_eq!(requests[0].header("Content-Type"), Some("application/json"));
+        let push: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let streams: Vec<(Value, Vec<(String, String)>)> = push["streams"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|stream| {
+                let values = stream["values"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|value| {
+                        let line: Value = serde_json::from_str(value[1].as_str().unwrap()).unwrap();
+                        (value[0].as_str().unwrap().to_string(), line["message"].as_str().unwrap().to_string())
+                    })
+                    .collect();
+                (stream["stream"].clone(), values)
+            })
+            .collect();
+
+        let nanos = |second: i64| ((1_709_622_480 + second) * 1_000_000_000).to_string();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].0, serde_json::json!({ "job": "test", "level": "debug", "source": "app::http" }));
+        assert_eq!(streams[0].1, [(nanos(2), "request".to_string())]);
+        assert_eq!(streams[1].0, serde_json::json!({ "job": "test", "level": "prod", "source": "app::db" }));
+        assert_eq!(
+            streams[1].1,
+            [
+                (nanos(1), "first".to_string()),
+                (nanos(2), "second".to_string()),
+                (nanos(3), "third".to_string())
+            ]
+        );
+        let stats = http::batcher_stats(&batcher).unwrap();
+        assert_eq!((stats.sent_batches, stats.sent_records, stats.dropped), (1, 4, 0));
+        sender::close(&batcher);
+    }
+
+    #[test]
+    fn label_fields_without_a_value_are_left_out() {
+        let output = LokiOutput::new("http://loki:3100").label_fields(&[LabelField::Source, LabelField::ThreadName]);
+        let entry = output.entry(&record(Level::Prod, "app", "hi", 0));
+        assert_eq!(entry.labels.into_iter().collect::<Vec<_>>(), [("source".to_string(), "app".to_string())]);
+    }
+}
@@ -4,12 +4,12 @@ macro_rules! log {
         let lvl = $lvl;
         if $crate::models::__private_api::log_enabled(lvl, $source) {
 
-            $crate::models::__private_api::log(
+            let _ = $crate::models::__private_api::log(
                 $msg.to_string(),
                 lvl,
-                &($source, models::__private_api::module_path!(), $crate::models::__private_api::file!()),
+                &($source, $crate::models::__private_api::module_path!(), $crate::models::__private_api::file!()),
                 $crate::models::__private_api::line!(),
-                $crate::models::__private_api::Option::Some(&[$(models::__log_key!($key, $value)),+]),
+                $crate::models::__private_api::Option::Some(&[$($crate::__log_key!($key, $value)),+]),
             );
         }
     });
@@ -17,7 +17,7 @@ macro_rules! log {
     (source: $source:expr, $lvl:expr, $msg:expr) => ({
         let lvl = $lvl;
         if $crate::models::__private_api::log_enabled(lvl, $source) {
-            $crate::models::__private_api::log(
+            let _ = $crate::models::__private_api::log(
                 $msg.to_string(),
                 lvl,
                 &($source, $crate::models::__private_api::module_path!(), $crate::models::__private_api::file!()),
@@ -36,7 +36,7 @@ macro_rules! log_console {
     ($source:expr, $lvl:expr, $thread:expr, $msg:expr) => ({
         let lvl = $lvl;
         if $crate::models::__private_api::log_enabled(lvl, $source) {
-            $crate::models::__private_api::log_console(
+            let _ = $crate::models::__private_api::log_console(
                 $msg.to_string(),
                 lvl,
                 $source.to_string(),
@@ -61,9 +61,9 @@ macro_rules! log_enabled {
 #[macro_export]
 macro_rules! __log_key {
     ($key:ident, $value:expr) => {
-        ($crate::models::__private_api::stringify!($key), &$value)
+        ($crate::models::__private_api::stringify!($key), $value.to_string())
     };
     ($key:expr, $value:expr) => {
-        ($key, &$value)
+        ($key, $value.to_string())
     };
 }
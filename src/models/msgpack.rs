@@ -0,0 +1,298 @@
+/// The MessagePack subset the Fluentd output writes and reads back.
+///
+/// Encoding always picks the smallest representation, as the spec asks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Ext(i8, Vec<u8>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value under the string key `key`, if this is a map holding it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(pairs) => pairs
+                .iter()
+                .find(|(name, _)| name.as_str() == Some(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+pub fn put_uint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0x7f => out.push(value as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, value as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xcf);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn put_length(out: &mut Vec<u8>, len: usize, fix: Option<(u8, usize)>, markers: [u8; 3]) {
+    match fix {
+        Some((base, max)) if len <= max => out.push(base | len as u8),
+        _ if len <= 0xff && markers[0] != 0 => out.extend_from_slice(&[markers[0], len as u8]),
+        _ if len <= 0xffff => {
+            out.push(markers[1]);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(markers[2]);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+pub fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_length(out, value.len(), Some((0xa0, 31)), [0xd9, 0xda, 0xdb]);
+    out.extend_from_slice(value.as_bytes());
+}
+
+pub fn put_bin(out: &mut Vec<u8>, value: &[u8]) {
+    put_length(out, value.len(), None, [0xc4, 0xc5, 0xc6]);
+    out.extend_from_slice(value);
+}
+
+pub fn put_array_len(out: &mut Vec<u8>, len: usize) {
+    put_length(out, len, Some((0x90, 15)), [0, 0xdc, 0xdd]);
+}
+
+pub fn put_map_len(out: &mut Vec<u8>, len: usize) {
+    put_length(out, len, Some((0x80, 15)), [0, 0xde, 0xdf]);
+}
+
+/// Extension types with an 8-byte payload, such as Fluentd's EventTime.
+pub fn put_fixext8(out: &mut Vec<u8>, kind: i8, payload: [u8; 8]) {
+    out.extend_from_slice(&[0xd7, kind as u8]);
+    out.extend_from_slice(&payload);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+/// Why [`decode`] could not produce a value.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// More bytes are needed; a stream reader should wait for them.
+    Incomplete,
+    Invalid(String),
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.offset.checked_add(len).ok_or(DecodeError::Incomplete)?;
+        let bytes = self.bytes.get(self.offset..end).ok_or(DecodeError::Incomplete)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, len: usize) -> Result<u64, DecodeError> {
+        Ok(self.take(len)?.iter().fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, DecodeError> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map(Value::Str)
+            .map_err(|_| DecodeError::Invalid("MessagePack string is not UTF-8".to_string()))
+    }
+
+    fn array(&mut self, len: usize) -> Result<Value, DecodeError> {
+        (0..len).map(|_| self.value()).collect::<Result<_, _>>().map(Value::Array)
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value, DecodeError> {
+        (0..len)
+            .map(|_| Ok((self.value()?, self.value()?)))
+            .collect::<Result<_, _>>()
+            .map(Value::Map)
+    }
+
+    fn ext(&mut self, len: usize) -> Result<Value, DecodeError> {
+        let kind = self.take(1)?[0] as i8;
+        Ok(Value::Ext(kind, self.take(len)?.to_vec()))
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        let marker = self.take(1)?[0];
+        match marker {
+            0x00..=0x7f => Ok(Value::UInt(marker as u64)),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize),
+            0x90..=0x9f => self.array((marker & 0x0f) as usize),
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize),
+            0xc0 => Ok(Value::Nil),
+            0xc2 => Ok(Value::Bool(false)),
+            0xc3 => Ok(Value::Bool(true)),
+            0xc4..=0xc6 => {
+                let len = self.uint(1 << (marker - 0xc4))? as usize;
+                Ok(Value::Bin(self.take(len)?.to_vec()))
+            }
+            0xc7..=0xc9 => {
+                let len = self.uint(1 << (marker - 0xc7))? as usize;
+                self.ext(len)
+            }
+            0xca => Ok(Value::Float(f32::from_bits(self.uint(4)? as u32) as f64)),
+            0xcb => Ok(Value::Float(f64::from_bits(self.uint(8)?))),
+            0xcc..=0xcf => Ok(Value::UInt(self.uint(1 << (marker - 0xcc))?)),
+            0xd0..=0xd3 => {
+                let len = 1 << (marker - 0xd0);
+                let shift = 64 - 8 * len as u32;
+                Ok(Value::Int(((self.uint(len)? << shift) as i64) >> shift))
+            }
+            0xd4..=0xd8 => self.ext(1 << (marker - 0xd4)),
+            0xd9..=0xdb => {
+                let len = self.uint(1 << (marker - 0xd9))? as usize;
+                self.string(len)
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(2 << (marker - 0xdc))? as usize;
+                self.array(len)
+            }
+            0xde | 0xdf => {
+                let len = self.uint(2 << (marker - 0xde))? as usize;
+                self.map(len)
+            }
+            0xe0..=0xff => Ok(Value::Int(marker as i8 as i64)),
+            0xc1 => Err(DecodeError::Invalid("0xc1 is not a MessagePack marker".to_string())),
+        }
+    }
+}
+
+/// Reads the value at the start of `bytes`, returning it and the number of
+/// bytes it took.
+pub fn decode(bytes: &[u8]) -> Result<(Value, usize), DecodeError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let value = reader.value()?;
+    Ok((value, reader.offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes `out` whole and checks its header took `header` bytes.
+    fn round_trip(out: &[u8], payload: usize, header: usize) -> Value {
+        assert_eq!(out.len(), payload + header, "header of {} bytes expected", header);
+        let (value, len) = decode(out).unwrap();
+        assert_eq!(len, out.len());
+        assert_eq!(decode(&out[..out.len() - 1]), Err(DecodeError::Incomplete));
+        value
+    }
+
+    #[test]
+    fn uints_round_trip_in_their_smallest_form() {
+        for (value, len) in [
+            (0, 1),
+            (0x7f, 1),
+            (0x80, 2),
+            (0xff, 2),
+            (0x100, 3),
+            (0xffff, 3),
+            (0x1_0000, 5),
+            (0xffff_ffff, 5),
+            (0x1_0000_0000, 9),
+            (u64::MAX, 9),
+        ] {
+            let mut out = Vec::new();
+            put_uint(&mut out, value);
+            assert_eq!(out.len(), len, "{:#x}", value);
+            assert_eq!(decode(&out), Ok((Value::UInt(value), len)));
+        }
+    }
+
+    #[test]
+    fn strings_and_binaries_round_trip_at_every_length_boundary() {
+        for (len, header) in [(0, 1), (31, 1), (32, 2), (255, 2), (256, 3), (65_535, 3), (65_536, 5)] {
+            let value = "é".repeat(len / 2) + &"x".repeat(len % 2);
+            let mut out = Vec::new();
+            put_str(&mut out, &value);
+            assert_eq!(round_trip(&out, len, header), Value::Str(value));
+        }
+        for (len, header) in [(0, 2), (255, 2), (256, 3), (65_535, 3), (65_536, 5)] {
+            let value: Vec<u8> = (0..len).map(|byte| byte as u8).collect();
+            let mut out = Vec::new();
+            put_bin(&mut out, &value);
+            assert_eq!(round_trip(&out, len, header), Value::Bin(value));
+        }
+    }
+
+    #[test]
+    fn arrays_and_maps_round_trip_at_every_length_boundary() {
+        for (len, header) in [(0, 1), (15, 1), (16, 3), (65_535, 3), (65_536, 5)] {
+            let mut out = Vec::new();
+            put_array_len(&mut out, len);
+            for item in 0..len {
+                put_uint(&mut out, (item % 0x80) as u64);
+            }
+            let items = (0..len).map(|item| Value::UInt((item % 0x80) as u64)).collect();
+            assert_eq!(round_trip(&out, len, header), Value::Array(items));
+        }
+        for (len, header) in [(0, 1), (15, 1), (16, 3)] {
+            let mut out = Vec::new();
+            put_map_len(&mut out, len);
+            let mut payload = 0;
+            for key in 0..len {
+                let before = out.len();
+                put_str(&mut out, &key.to_string());
+                put_uint(&mut out, key as u64);
+                payload += out.len() - before;
+            }
+            let pairs = (0..len)
+                .map(|key| (Value::Str(key.to_string()), Value::UInt(key as u64)))
+                .collect();
+            let map = round_trip(&out, payload, header);
+            assert_eq!(map.get("3").is_some(), len > 3);
+            assert_eq!(map, Value::Map(pairs));
+        }
+    }
+
+    #[test]
+    fn nested_values_and_extensions_round_trip() {
+        let mut out = Vec::new();
+        put_array_len(&mut out, 2);
+        put_fixext8(&mut out, 0, [0, 0, 0, 1, 0, 0, 0, 2]);
+        put_map_len(&mut out, 1);
+        put_str(&mut out, "ack");
+        put_str(&mut out, "chunk");
+        let (value, len) = decode(&out).unwrap();
+        assert_eq!(len, out.len());
+        let Value::Array(items) = value else {
+            panic!("not an array: {:?}", value);
+        };
+        assert_eq!(items[0], Value::Ext(0, vec![0, 0, 0, 1, 0, 0, 0, 2]));
+        assert_eq!(items[1].get("ack").and_then(Value::as_str), Some("chunk"));
+    }
+
+    #[test]
+    fn reserved_marker_is_invalid() {
+        assert!(matches!(decode(&[0xc1]), Err(DecodeError::Invalid(_))));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::models::compression::Compression;
+use crate::models::constants::DATE_FORMAT;
+use crate::models::error::LoggerError;
+use crate::models::level::Level;
+use crate::models::schedule::Schedule;
+use chrono::{DateTime, TimeZone};
+use once_cell::sync::Lazy;
+use std::fmt;
+use std::str::FromStr;
+
+pub const DEFAULT_FILE_NAME_PATTERN: &str = "{date}_{level}.log";
+
+/// What `{level}` renders to for files that are not tied to a single level.
+pub const ALL_LEVELS_LABEL: &str = "All";
+
+pub(crate) static HOSTNAME: Lazy<String> = Lazy::new(hostname);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Date,
+    Week,
+    Hour,
+    Level,
+    Source,
+    Pid,
+    Hostname,
+}
+
+/// A compiled file name template such as `{date}_{level}.log`.
+///
+/// Supported tokens are `{date}`, `{week}` (the ISO week, e.g. `2024-W07`),
+/// `{hour}`, `{level}`, `{source}`, `{pid}` and `{hostname}`. Use `{{` and
+/// `}}` for literal braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileNamePattern {
+    pattern: String,
+    segments: Vec<Segment>,
+}
+
+impl FileNamePattern {
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Renders the name of one file. `level` is `None` for files that collect
+    /// every level, which renders `{level}` as [`ALL_LEVELS_LABEL`].
+    pub fn render<Tz: TimeZone>(&self, time: &DateTime<Tz>, level: Option<Level>, source: &str) -> String
+    where
+        Tz::Offset: fmt::Display,
+    {
+        let mut name = String::with_capacity(self.pattern.len() + 16);
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => name.push_str(s),
+                Segment::Date => name.push_str(&time.format(DATE_FORMAT).to_string()),
+                Segment::Week => name.push_str(&time.format("%G-W%V").to_string()),
+                Segment::Hour => name.push_str(&time.format("%H").to_string()),
+                Segment::Level => match level {
+                    Some(level) => name.push_str(&format!("{:?}", level)),
+                    None => name.push_str(ALL_LEVELS_LABEL),
+                },
+                Segment::Source => name.push_str(&sanitize(source)),
+                Segment::Pid => name.push_str(&std::process::id().to_string()),
+                Segment::Hostname => name.push_str(&sanitize(&HOSTNAME)),
+            }
+        }
+        name
+    }
+
+    /// Whether `file_name` is one of ours: a name this pattern renders, possibly
+    /// followed by a backup index (`.3`) and a compression extension (`.gz`).
+    pub fn matches_file(&self, file_name: &str) -> bool {
+        let without_archive = Compression::EXTENSIONS
+            .iter()
+            .find_map(|ext| file_name.strip_suffix(ext)?.strip_suffix('.'))
+            .unwrap_or(file_name);
+        let without_index = without_archive
+            .rsplit_once('.')
+            .filter(|(_, index)| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+            .map_or(without_archive, |(base, _)| base);
+        [file_name, without_archive, without_index]
+            .iter()
+            .any(|candidate| match_segments(&self.segments, candidate))
+    }
+
+    /// Whether every boundary of `schedule` already yields a new file name, so
+    /// rolling over only means closing the old file rather than renaming it.
+    pub fn changes_every(&self, schedule: Schedule) -> bool {
+        let has = |wanted: &Segment| self.segments.iter().any(|s| s == wanted);
+        match schedule {
+            Schedule::Minutely => false,
+            Schedule::Hourly => has(&Segment::Date) && has(&Segment::Hour),
+            Schedule::Daily => has(&Segment::Date),
+            Schedule::Weekly => has(&Segment::Week),
+        }
+    }
+
+    /// Whether the name changes before a period of `schedule` is over, e.g.
+    /// `{date}` with weekly rotation, which would start a file every day.
+    pub fn changes_within(&self, schedule: Schedule) -> bool {
+        let has = |wanted: &Segment| self.segments.iter().any(|s| s == wanted);
+        match schedule {
+            Schedule::Minutely | Schedule::Hourly => false,
+            Schedule::Daily => has(&Segment::Hour),
+            Schedule::Weekly => has(&Segment::Date) || has(&Segment::Hour),
+        }
+    }
+}
+
+impl Default for FileNamePattern {
+    fn default() -> Self {
+        FileNamePattern {
+            pattern: DEFAULT_FILE_NAME_PATTERN.to_string(),
+            segments: vec![
+                Segment::Date,
+                Segment::Literal("_".to_string()),
+                Segment::Level,
+                Segment::Literal(".log".to_string()),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for FileNamePattern {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.pattern)
+    }
+}
+
+impl FromStr for FileNamePattern {
+    type Err = LoggerError;
+
+    fn from_str(pattern: &str) -> Result<FileNamePattern, Self::Err> {
+        let invalid = |why: &str| LoggerError::InvalidConfig(format!("file name pattern `{}`: {}", pattern, why));
+        if pattern.is_empty() {
+            return Err(invalid("pattern is empty"));
+        }
+        if pattern.contains('/') || pattern.contains('\\') {
+            return Err(invalid("pattern must not contain path separators"));
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut token = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => token.push(c),
+                            None => return Err(invalid("unclosed `{`")),
+                        }
+                    }
+                    let segment = match token.as_str() {
+                        "date" => Segment::Date,
+                        "week" => Segment::Week,
+                        "hour" => Segment::Hour,
+                        "level" => Segment::Level,
+                        "source" => Segment::Source,
+                        "pid" => Segment::Pid,
+                        "hostname" => Segment::Hostname,
+                        other => return Err(invalid(&format!("unknown token `{{{}}}`", other))),
+                    };
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                }
+                '}' => return Err(invalid("unmatched `}`")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(FileNamePattern {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+}
+
+fn match_segments(segments: &[Segment], name: &str) -> bool {
+    let Some((segment, rest)) = segments.split_first() else {
+        return name.is_empty();
+    };
+    let digits = |name: &str| name.bytes().take_while(u8::is_ascii_digit).count();
+    match segment {
+        Segment::Literal(literal) => name
+            .strip_prefix(literal.as_str())
+            .is_some_and(|name| match_segments(rest, name)),
+        Segment::Date => {
+            let date = name.as_bytes().get(..10);
+            let is_date = date.is_some_and(|d| {
+                d.iter().enumerate().all(|(i, b)| if i == 4 || i == 7 { *b == b'-' } else { b.is_ascii_digit() })
+            });
+            is_date && match_segments(rest, &name[10..])
+        }
+        Segment::Week => {
+            let week = name.as_bytes().get(..8);
+            let is_week = week.is_some_and(|w| {
+                w.iter().enumerate().all(|(i, b)| match i {
+                    4 => *b == b'-',
+                    5 => *b == b'W',
+                    _ => b.is_ascii_digit(),
+                })
+            });
+            is_week && match_segments(rest, &name[8..])
+        }
+        Segment::Hour => digits(name) >= 2 && match_segments(rest, &name[2..]),
+        Segment::Level => Level::iter()
+            .map(|level| format!("{:?}", level))
+            .chain(std::iter::once(ALL_LEVELS_LABEL.to_string()))
+            .any(|label| name.strip_prefix(&label).is_some_and(|name| match_segments(rest, name))),
+        Segment::Pid => (1..=digits(name)).any(|len| match_segments(rest, &name[len..])),
+        Segment::Hostname => name
+            .strip_prefix(sanitize(&HOSTNAME).as_str())
+            .is_some_and(|name| match_segments(rest, name)),
+        Segment::Source => {
+            let longest = name
+                .char_indices()
+                .find(|&(_, c)| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                .map_or(name.len(), |(i, _)| i);
+            name[..longest]
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| i)
+                .chain(std::iter::once(longest).filter(|&l| l > 0))
+                .any(|len| match_segments(rest, &name[len..]))
+        }
+    }
+}
+
+/// Keeps a token value usable as part of a single file name.
+fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    if cleaned.is_empty() || cleaned.chars().all(|c| c == '.') {
+        "_".to_string()
+    } else {
+        cleaned
+    }
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if rc == 0 {
+        let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        if let Ok(name) = std::str::from_utf8(&buf[..end]) {
+            if !name.is_empty() {
+                return name.to_string();
+            }
+        }
+    }
+    "localhost".to_string()
+}
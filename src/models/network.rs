@@ -0,0 +1,332 @@
+use crate::models::binary;
+use crate::models::error::LoggerError;
+use crate::models::format::json_line;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::sender::{self, Backoff, Batching, Sender};
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// A peer that stops reading counts as lost after this long.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where a sender thread is kept; the network and remote outputs each have one.
+pub(crate) type Stream = sender::Slot<NetworkOutput>;
+
+lazy_static::lazy_static! {
+    static ref STREAM: Stream = Mutex::new(None);
+}
+
+/// Where the stream goes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NetworkTarget {
+    /// `host:port`.
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+/// The [`NetworkTarget`] variants as seen from C; the address travels separately.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NetworkTargetKind {
+    Tcp,
+    Unix,
+}
+
+impl NetworkTarget {
+    pub fn new(kind: NetworkTargetKind, address: &str) -> Result<NetworkTarget, LoggerError> {
+        if address.is_empty() {
+            return Err(LoggerError::InvalidConfig("network address is empty".to_string()));
+        }
+        Ok(match kind {
+            NetworkTargetKind::Tcp => NetworkTarget::Tcp(address.to_string()),
+            NetworkTargetKind::Unix => NetworkTarget::Unix(PathBuf::from(address)),
+        })
+    }
+}
+
+impl FromStr for NetworkTargetKind {
+    type Err = LoggerError;
+
+    fn from_str(kind: &str) -> Result<NetworkTargetKind, Self::Err> {
+        match kind.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(NetworkTargetKind::Tcp),
+            "unix" => Ok(NetworkTargetKind::Unix),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown network target `{}`", kind))),
+        }
+    }
+}
+
+impl fmt::Display for NetworkTarget {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkTarget::Tcp(address) => write!(fmt, "tcp://{}", address),
+            NetworkTarget::Unix(path) => write!(fmt, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// How records are delimited on the stream.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Framing {
+    /// One JSON object per line, as the `Json` file format writes them.
+    #[default]
+    JsonLines,
+    /// A big-endian `u32` length, then the record in the binary format's
+    /// record layout with its source inline.
+    LengthPrefixed,
+}
+
+impl FromStr for Framing {
+    type Err = LoggerError;
+
+    fn from_str(framing: &str) -> Result<Framing, Self::Err> {
+        match framing.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "jsonlines" | "ndjson" | "json" => Ok(Framing::JsonLines),
+            "lengthprefixed" | "binary" => Ok(Framing::LengthPrefixed),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown framing `{}`", framing))),
+        }
+    }
+}
+
+/// Streams records to a TCP or Unix socket from a thread of its own.
+///
+/// While the peer cannot be reached, records wait in a queue of at most
+/// `queue_capacity` records, the oldest dropped first, and the connection is
+/// retried with exponential backoff from 100 ms up to 30 s. Logging never
+/// waits for the network; see [`stats`](crate::models::stats::stats) for the
+/// connection state. Records written just before the peer went away can
+/// still be lost: the socket accepts them and only the next write fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetworkOutput {
+    pub(crate) target: NetworkTarget,
+    pub(crate) framing: Framing,
+    pub(crate) queue_capacity: usize,
+    pub(crate) max_level: LevelFilter,
+    /// Written first on every connection, before any record.
+    pub(crate) greeting: Option<Vec<u8>>,
+}
+
+impl NetworkOutput {
+    pub fn new(target: NetworkTarget) -> NetworkOutput {
+        NetworkOutput {
+            target,
+            framing: Framing::default(),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            max_level: LevelFilter::Trace,
+            greeting: None,
+        }
+    }
+
+    pub fn framing(mut self, framing: Framing) -> NetworkOutput {
+        self.framing = framing;
+        self
+    }
+
+    /// Records kept while disconnected before the oldest are dropped.
+    pub fn queue_capacity(mut self, capacity: usize) -> NetworkOutput {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> NetworkOutput {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn target(&self) -> &NetworkTarget {
+        &self.target
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+
+    pub(crate) fn frame(&self, record: &LogData) -> Vec<u8> {
+        match self.framing {
+            Framing::JsonLines => {
+                let mut line = json_line(record).into_bytes();
+                line.push(b'\n');
+                line
+            }
+            Framing::LengthPrefixed => {
+                let body = binary::encode_body(record, None);
+                let mut frame = Vec::with_capacity(4 + body.len());
+                frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+                frame.extend_from_slice(&body);
+                frame
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// The last attempt failed; the next one waits for the backoff.
+    Disconnected,
+}
+
+/// How the network output is doing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct NetworkStats {
+    pub target: String,
+    pub state: ConnectionState,
+    /// Records waiting to be sent.
+    pub queued: usize,
+    pub sent: u64,
+    /// Records dropped because the queue was full.
+    pub dropped: u64,
+    /// Connections established after the first one.
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Connection {
+    fn open(target: &NetworkTarget) -> io::Result<Connection> {
+        match target {
+            NetworkTarget::Tcp(address) => {
+                let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("{} did not resolve", address));
+                for address in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                        Ok(stream) => {
+                            stream.set_nodelay(true)?;
+                            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                            return Ok(Connection::Tcp(stream));
+                        }
+                        Err(e) => last_error = e,
+                    }
+                }
+                Err(last_error)
+            }
+            NetworkTarget::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                Ok(Connection::Unix(stream))
+            }
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.write_all(bytes),
+            Connection::Unix(stream) => stream.write_all(bytes),
+        }
+    }
+}
+
+impl sender::Output for NetworkOutput {
+    type Record = Vec<u8>;
+    const KIND: &'static str = "network";
+
+    fn destination(&self) -> String {
+        self.target.to_string()
+    }
+
+    fn queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    fn batching(&self) -> Batching {
+        Batching::IMMEDIATE
+    }
+
+    fn record_len(frame: &Vec<u8>) -> usize {
+        frame.len()
+    }
+
+    /// Writes frames as they are queued, opening every connection with the
+    /// greeting. A frame that failed may have been cut short; it is sent
+    /// again, whole, on the next connection, ahead of everything queued since.
+    fn run(sender: Arc<Sender<NetworkOutput>>) {
+        let open = |output: &NetworkOutput| {
+            let mut connection = Connection::open(&output.target)?;
+            if let Some(greeting) = &output.greeting {
+                connection.write_all(greeting)?;
+            }
+            Ok(connection)
+        };
+        sender::run_connected(
+            &sender,
+            Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF),
+            open,
+            |sender, connection, frames| {
+                let mut written = 0;
+                let mut failure = None;
+                for (_, frame) in frames.iter() {
+                    if let Err(e) = connection.write_all(frame) {
+                        failure = Some(e);
+                        break;
+                    }
+                    written += 1;
+                }
+                frames.drain(..written);
+                sender.delivered(written);
+                failure.map_or(Ok(()), Err)
+            },
+        );
+    }
+}
+
+/// Queues `record` for the sender thread; never waits for the network.
+pub(crate) fn send(output: &NetworkOutput, record: &LogData) {
+    if output.accepts(record.level) {
+        sender::enqueue(&STREAM, output, output.frame(record));
+    }
+}
+
+/// Waits until `deadline` for queued records to be sent, as long as the
+/// connection is up; records for an unreachable peer are not waited for.
+pub(crate) fn flush(deadline: Instant) {
+    sender::flush(&STREAM, deadline);
+}
+
+/// Stops the sender thread once it has made one last attempt at what it still holds.
+pub(crate) fn close() {
+    sender::close(&STREAM);
+}
+
+/// Stops the sender thread, waiting until `deadline` for what it still holds to go out.
+pub(crate) fn shut_down(deadline: Instant) {
+    sender::shut_down(&STREAM, deadline);
+}
+
+/// The current sender's numbers, if a network output has been used.
+pub(crate) fn stats() -> Option<NetworkStats> {
+    stream_stats(&STREAM)
+}
+
+pub(crate) fn stream_stats(stream: &Stream) -> Option<NetworkStats> {
+    sender::stats(stream, |sender, queue| NetworkStats {
+        target: sender.output.target.to_string(),
+        state: queue.state,
+        queued: queue.queued(),
+        sent: sender.sent.load(Ordering::Relaxed),
+        dropped: sender.dropped.load(Ordering::Relaxed),
+        reconnects: sender.reconnects.load(Ordering::Relaxed),
+        last_error: queue.last_error.clone(),
+    })
+}
@@ -0,0 +1,224 @@
+use crate::models::level::Level;
+use crate::models::logger::LogData;
+use crate::models::naming::HOSTNAME;
+use crate::models::syslog::default_app_name;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+/// Key-value pairs under these names become the record's trace context when
+/// they hold a valid id: 32 and 16 hex digits respectively, not all zero.
+pub const TRACE_ID_KEY: &str = "trace_id";
+pub const SPAN_ID_KEY: &str = "span_id";
+
+/// Who sends the records: `service.name`, from `OTEL_SERVICE_NAME` or else
+/// the running executable's name, `host.name`, `process.pid` and the
+/// `telemetry.sdk.*` attributes.
+static RESOURCE: Lazy<Value> = Lazy::new(|| {
+    let service = std::env::var("OTEL_SERVICE_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(default_app_name);
+    json!({
+        "attributes": [
+            string_attribute("service.name", &service),
+            string_attribute("host.name", &HOSTNAME),
+            int_attribute("process.pid", std::process::id() as i64),
+            string_attribute("telemetry.sdk.name", env!("CARGO_PKG_NAME")),
+            string_attribute("telemetry.sdk.language", "rust"),
+            string_attribute("telemetry.sdk.version", env!("CARGO_PKG_VERSION")),
+        ]
+    })
+});
+
+/// The OpenTelemetry severity number for `level`: `Prod` is `INFO` (9),
+/// `Debug` is `DEBUG` (5) and `Trace` is `TRACE` (1), each the lowest number
+/// of its range, so severities compare the same way our levels do. The
+/// severity text is the level's own name.
+pub fn severity_number(level: Level) -> u8 {
+    match level {
+        Level::Prod => 9,
+        Level::Debug => 5,
+        Level::Trace => 1,
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// 64-bit integers travel as strings in OTLP JSON.
+fn int_attribute(key: &str, value: i64) -> Value {
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+fn hex_id(value: &str, len: usize) -> Option<String> {
+    let valid = value.len() == len
+        && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+        && value.bytes().any(|byte| byte != b'0');
+    valid.then(|| value.to_ascii_lowercase())
+}
+
+fn nanos(time: chrono::DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+/// `record` as an OTLP JSON `LogRecord`.
+///
+/// `timeUnixNano` is when the record was logged and `observedTimeUnixNano`
+/// when it was encoded; the body is the message. Attributes are every
+/// key-value pair, as strings, then `code.filepath`, `code.lineno`,
+/// `code.namespace`, `thread.id` and `thread.name`. A valid `trace_id` or
+/// `span_id` pair becomes the record's `traceId` or `spanId` instead of an
+/// attribute.
+pub fn log_record(record: &LogData) -> Value {
+    let mut attributes = Vec::with_capacity(record.key_values.len() + 5);
+    let mut trace_id = None;
+    let mut span_id = None;
+    for (key, value) in &record.key_values {
+        let id = match key.as_str() {
+            TRACE_ID_KEY if trace_id.is_none() => &mut trace_id,
+            SPAN_ID_KEY if span_id.is_none() => &mut span_id,
+            _ => {
+                attributes.push(string_attribute(key, value));
+                continue;
+            }
+        };
+        *id = hex_id(value, if key == TRACE_ID_KEY { 32 } else { 16 });
+        if id.is_none() {
+            attributes.push(string_attribute(key, value));
+        }
+    }
+    if let Some(file) = &record.file {
+        attributes.push(string_attribute("code.filepath", file));
+    }
+    if let Some(line) = record.line {
+        attributes.push(int_attribute("code.lineno", line as i64));
+    }
+    if let Some(module_path) = &record.module_path {
+        attributes.push(string_attribute("code.namespace", module_path));
+    }
+    attributes.push(int_attribute("thread.id", record.thread as i64));
+    if let Some(name) = &record.thread_name {
+        attributes.push(string_attribute("thread.name", name));
+    }
+
+    let mut log_record = json!({
+        "timeUnixNano": nanos(record.time),
+        "observedTimeUnixNano": nanos(Utc::now()),
+        "severityNumber": severity_number(record.level),
+        "severityText": record.level.as_str(),
+        "body": { "stringValue": record.message },
+        "attributes": attributes,
+    });
+    if let Some(trace_id) = trace_id {
+        log_record["traceId"] = Value::from(trace_id);
+    }
+    if let Some(span_id) = span_id {
+        log_record["spanId"] = Value::from(span_id);
+    }
+    log_record
+}
+
+/// `record` as it is queued for [`export_request`]: its source, the
+/// instrumentation scope, as a JSON string, a newline, then its `LogRecord`.
+/// JSON strings cannot hold a raw newline, so the first one splits the two.
+pub fn scoped_log_record(record: &LogData) -> Vec<u8> {
+    let mut scoped = Value::from(record.source.as_str()).to_string().into_bytes();
+    scoped.push(b'\n');
+    scoped.extend_from_slice(log_record(record).to_string().as_bytes());
+    scoped
+}
+
+/// An `ExportLogsServiceRequest` holding `records`, each from
+/// [`scoped_log_record`], under this process's resource: one `ScopeLogs`
+/// per scope, in the order scopes first appear, with its records in order.
+pub fn export_request(records: &[Vec<u8>]) -> Vec<u8> {
+    let mut scopes: Vec<(&[u8], Vec<&[u8]>)> = Vec::new();
+    for record in records {
+        let split = record.iter().position(|&byte| byte == b'\n').unwrap_or(record.len());
+        let (scope, log_record) = (&record[..split], record.get(split + 1..).unwrap_or_default());
+        match scopes.iter_mut().find(|(name, _)| *name == scope) {
+            Some((_, log_records)) => log_records.push(log_record),
+            None => scopes.push((scope, vec![log_record])),
+        }
+    }
+
+    let resource = RESOURCE.to_string();
+    let len = records.iter().map(|record| record.len() + 1).sum::<usize>() + 48 * scopes.len();
+    let mut body = Vec::with_capacity(len + resource.len() + 64);
+    body.extend_from_slice(b"{\"resourceLogs\":[{\"resource\":");
+    body.extend_from_slice(resource.as_bytes());
+    body.extend_from_slice(b",\"scopeLogs\":[");
+    for (index, (scope, log_records)) in scopes.iter().enumerate() {
+        if index > 0 {
+            body.push(b',');
+        }
+        body.extend_from_slice(b"{\"scope\":{\"name\":");
+        body.extend_from_slice(scope);
+        body.extend_from_slice(b"},\"logRecords\":[");
+        for (index, log_record) in log_records.iter().enumerate() {
+            if index > 0 {
+                body.push(b',');
+            }
+            body.extend_from_slice(log_record);
+        }
+        body.extend_from_slice(b"]}");
+    }
+    body.extend_from_slice(b"]}]}");
+    body
+}
+
+/// `record` as a whole `ExportLogsServiceRequest` on one line, the layout the
+/// Collector's `otlpjsonfile` receiver reads.
+pub fn export_line(record: &LogData) -> String {
+    String::from_utf8(export_request(&[scoped_log_record(record)])).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(source: &str, message: &str) -> LogData {
+        LogData::test(Level::Prod, source, message)
+            .key_values(&[(TRACE_ID_KEY, "4BF92F3577B34DA6A3CE929D0E0E4736")])
+    }
+
+    #[test]
+    fn export_requests_group_records_by_scope() {
+        let records: Vec<Vec<u8>> = [("app::db", "a"), ("odd \"scope\"\n", "b"), ("app::db", "c")]
+            .iter()
+            .map(|(source, message)| scoped_log_record(&record(source, message)))
+            .collect();
+        let request: Value = serde_json::from_slice(&export_request(&records)).unwrap();
+
+        let resource_logs = request["resourceLogs"].as_array().unwrap();
+        assert_eq!(resource_logs.len(), 1);
+        let scopes: Vec<(&str, Vec<&str>)> = resource_logs[0]["scopeLogs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|scope| {
+                let messages = scope["logRecords"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|log_record| log_record["body"]["stringValue"].as_str().unwrap())
+                    .collect();
+                (scope["scope"]["name"].as_str().unwrap(), messages)
+            })
+            .collect();
+        assert_eq!(scopes, [("app::db", vec!["a", "c"]), ("odd \"scope\"\n", vec!["b"])]);
+        let log_record = &resource_logs[0]["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(log_record["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(log_record["severityNumber"], 9);
+    }
+
+    #[test]
+    fn export_lines_hold_one_record() {
+        let line: Value = serde_json::from_str(&export_line(&record("app", "hi"))).unwrap();
+        let scope_logs = &line["resourceLogs"][0]["scopeLogs"];
+        assert_eq!(scope_logs[0]["scope"]["name"], "app");
+        assert_eq!(scope_logs[0]["logRecords"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Name of the lock file every cooperating process locks in the log directory.
+pub const LOCK_FILE_NAME: &str = ".common_logger.lock";
+
+lazy_static::lazy_static! {
+    static ref LOCK_FILES: Mutex<LockFiles> = Mutex::new(LockFiles::default());
+}
+
+/// Lock files by canonical path, and by each directory spelling already
+/// resolved, so the path is only canonicalized once.
+#[derive(Default)]
+struct LockFiles {
+    by_path: HashMap<PathBuf, Arc<LockFile>>,
+    by_directory: HashMap<PathBuf, Arc<LockFile>>,
+}
+
+/// One directory's lock file. `flock` only excludes other open file
+/// descriptions, so threads of this process that share the file are kept
+/// apart by `held` instead; other directories are not held up.
+struct LockFile {
+    file: File,
+    held: Mutex<bool>,
+    released: Condvar,
+}
+
+/// Exclusive access to a log directory across every process that logs into
+/// it. Released when dropped.
+pub(crate) struct DirectoryLock {
+    lock: Arc<LockFile>,
+}
+
+impl Drop for DirectoryLock {
+    fn drop(&mut self) {
+        unsafe {
+            libc::flock(self.lock.file.as_raw_fd(), libc::LOCK_UN);
+        }
+        *self.lock.held.lock().unwrap_or_else(PoisonError::into_inner) = false;
+        self.lock.released.notify_one();
+    }
+}
+
+/// The lock file for `directory`, shared by every spelling of its path.
+fn lock_file(directory: &Path) -> io::Result<Arc<LockFile>> {
+    let mut files = LOCK_FILES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(lock) = files.by_directory.get(directory) {
+        return Ok(Arc::clone(lock));
+    }
+    let path = directory.join(LOCK_FILE_NAME);
+    let file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(false)
+        .open(&path)?;
+    let lock = files
+        .by_path
+        .entry(fs::canonicalize(&path)?)
+        .or_insert_with(|| {
+            Arc::new(LockFile {
+                file,
+                held: Mutex::new(false),
+                released: Condvar::new(),
+            })
+        })
+        .clone();
+    files.by_directory.insert(directory.to_path_buf(), Arc::clone(&lock));
+    Ok(lock)
+}
+
+/// Blocks until this thread holds the lock on `directory`.
+pub(crate) fn lock_directory(directory: &Path) -> io::Result<DirectoryLock> {
+    let lock = lock_file(directory)?;
+    let mut held = lock.held.lock().unwrap_or_else(PoisonError::into_inner);
+    while *held {
+        held = lock.released.wait(held).unwrap_or_else(PoisonError::into_inner);
+    }
+    *held = true;
+    drop(held);
+    // From here the guard releases `held` again, also when `flock` fails.
+    let locked = DirectoryLock { lock };
+    loop {
+        if unsafe { libc::flock(locked.lock.file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(locked);
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+/// Locks the directory holding `path`, but only when `enabled`.
+pub(crate) fn lock_parent_if(enabled: bool, path: &Path) -> io::Result<Option<DirectoryLock>> {
+    if !enabled {
+        return Ok(None);
+    }
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    lock_directory(directory).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn directories_are_locked_independently() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let held = lock_directory(first.path()).unwrap();
+
+        let (locked, other) = mpsc::channel();
+        let (first_path, second_path) = (first.path().to_path_buf(), second.path().to_path_buf());
+        thread::spawn(move || {
+            drop(lock_directory(&second_path).unwrap());
+            locked.send("second").unwrap();
+            let spelled = first_path.join("..").join(first_path.file_name().unwrap());
+            drop(lock_directory(&spelled).unwrap());
+            locked.send("first").unwrap();
+        });
+        assert_eq!(other.recv_timeout(Duration::from_secs(5)), Ok("second"));
+        // The same directory under another spelling waits for the holder.
+        assert!(other.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(held);
+        assert_eq!(other.recv_timeout(Duration::from_secs(5)), Ok("first"));
+    }
+}
@@ -17,6 +17,7 @@ pub struct LogRecord<'x> {
     pub(crate) module_path: Option<StaticStr<'x>>,
     pub(crate) file: Option<StaticStr<'x>>,
     pub(crate) line: Option<u32>,
+    pub(crate) kvs: Vec<(String, String)>,
     #[cfg(feature = "kv_unstable")]
     key_values: KeyValues<'x>,
 }
@@ -45,6 +46,7 @@ impl<'x> LogRecordBuilder<'x> {
                 module_path: None,
                 file: None,
                 line: None,
+                kvs: Vec::new(),
                 #[cfg(feature = "kv_unstable")]
                 key_values: KeyValues(&Option::None::<(kv::Key, kv::Value)>),
             },
@@ -105,6 +107,12 @@ impl<'x> LogRecordBuilder<'x> {
         self
     }
 
+    #[inline]
+    pub fn kvs(&mut self, kvs: &[(&str, String)]) -> &mut LogRecordBuilder<'x> {
+        self.record.kvs = kvs.iter().map(|(key, value)| (key.to_string(), value.clone())).collect();
+        self
+    }
+
     #[cfg(feature = "kv_unstable")]
     #[inline]
     pub fn key_values(&mut self, kvs: &'x dyn kv::Source) -> &mut LogRecordBuilder<'x> {
@@ -0,0 +1,198 @@
+use crate::models::binary;
+use crate::models::error::LoggerError;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::{self, LogData};
+use crate::models::network::{self, Framing, NetworkOutput, NetworkStats, NetworkTarget};
+use crate::models::sender;
+use crate::models::syslog::default_app_name;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// What the first frame on every connection starts with, followed by the client's name.
+const GREETING_MAGIC: &[u8; 4] = b"ULC1";
+/// Frames longer than this are taken for a confused client and end the connection.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+/// The key-value pairs the collector adds to every record it receives.
+pub const CLIENT_PID_KEY: &str = "client_pid";
+pub const CLIENT_NAME_KEY: &str = "client_name";
+
+lazy_static::lazy_static! {
+    static ref STREAM: network::Stream = Mutex::new(None);
+}
+
+/// Hands records to a `ul-collectord` daemon on a Unix socket, which writes
+/// the records of every client process through its own configuration.
+///
+/// The connection starts with a greeting naming the client; records follow
+/// in the network output's length-prefixed framing, from a thread of its
+/// own, with the same queueing and reconnecting. The daemon adds the
+/// client's pid and name to each record as `client_pid` and `client_name`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteOutput {
+    pub(crate) stream: NetworkOutput,
+    pub(crate) client_name: String,
+}
+
+impl RemoteOutput {
+    /// Sends to the daemon listening on `socket`, under the running executable's name.
+    pub fn new<P: Into<PathBuf>>(socket: P) -> RemoteOutput {
+        let stream = NetworkOutput::new(NetworkTarget::Unix(socket.into())).framing(Framing::LengthPrefixed);
+        RemoteOutput { stream, client_name: String::new() }.client_name(&default_app_name())
+    }
+
+    /// The name the daemon adds to this process's records.
+    pub fn client_name(mut self, name: &str) -> RemoteOutput {
+        self.client_name = name.to_string();
+        let mut greeting = Vec::with_capacity(8 + name.len());
+        greeting.extend_from_slice(&((GREETING_MAGIC.len() + name.len()) as u32).to_be_bytes());
+        greeting.extend_from_slice(GREETING_MAGIC);
+        greeting.extend_from_slice(name.as_bytes());
+        self.stream.greeting = Some(greeting);
+        self
+    }
+
+    /// Records kept while the daemon cannot be reached before the oldest are dropped.
+    pub fn queue_capacity(mut self, capacity: usize) -> RemoteOutput {
+        self.stream = self.stream.queue_capacity(capacity);
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> RemoteOutput {
+        self.stream = self.stream.max_level(max_level);
+        self
+    }
+
+    #[inline]
+    pub fn socket(&self) -> &NetworkTarget {
+        self.stream.target()
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        self.stream.accepts(level)
+    }
+}
+
+/// Queues `record` for the daemon; never waits for it.
+pub(crate) fn send(output: &RemoteOutput, record: &LogData) {
+    if output.accepts(record.level) {
+        sender::enqueue(&STREAM, &output.stream, output.stream.frame(record));
+    }
+}
+
+/// Waits until `deadline` for queued records to reach the daemon, while it is reachable.
+pub(crate) fn flush(deadline: Instant) {
+    sender::flush(&STREAM, deadline);
+}
+
+/// Stops the sender thread once it has made one last attempt at what it still holds.
+pub(crate) fn close() {
+    sender::close(&STREAM);
+}
+
+/// Stops the sender thread, waiting until `deadline` for what it still holds to go out.
+pub(crate) fn shut_down(deadline: Instant) {
+    sender::shut_down(&STREAM, deadline);
+}
+
+/// The current sender's numbers, if a remote output has been used.
+pub(crate) fn stats() -> Option<NetworkStats> {
+    network::stream_stats(&STREAM)
+}
+
+/// Listens on `socket`, replacing a socket file a previous daemon left
+/// behind; fails if another daemon still answers on it.
+pub fn listen(socket: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another collector listens on {}", socket.display()),
+        ));
+    }
+    match std::fs::remove_file(socket) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    UnixListener::bind(socket)
+}
+
+/// Accepts remote output clients on `listener`, each on a thread of its
+/// own, and queues their records for this process's workers, so they go
+/// through the same routes, formats and outputs as records logged here.
+/// Returns only if the listener fails.
+pub fn serve(listener: UnixListener) -> Result<(), LoggerError> {
+    for client in listener.incoming() {
+        let client = client?;
+        thread::Builder::new()
+            .name("common_logger-collector".to_string())
+            .spawn(move || collect(client))?;
+    }
+    Ok(())
+}
+
+/// The pid of the process at the other end, as the kernel reports it.
+fn peer_pid(stream: &UnixStream) -> io::Result<u32> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+    let found = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if found != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.pid as u32)
+}
+
+/// The next frame's body, or `None` once the client closed the connection.
+fn read_frame(stream: &mut UnixStream) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        read => read?,
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes", len)));
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Reads one client's greeting, then its records until it disconnects.
+fn collect(mut stream: UnixStream) {
+    let pid = peer_pid(&stream).unwrap_or_default();
+    let received = (|| -> Result<(), LoggerError> {
+        let name = match read_frame(&mut stream)? {
+            Some(greeting) if greeting.starts_with(GREETING_MAGIC) => {
+                String::from_utf8_lossy(&greeting[GREETING_MAGIC.len()..]).into_owned()
+            }
+            Some(_) => return Err(LoggerError::InvalidData("client did not greet".to_string())),
+            None => return Ok(()),
+        };
+        let sources = HashMap::new();
+        while let Some(body) = read_frame(&mut stream)? {
+            let mut record = binary::decode_body(&body, &sources)?;
+            record.key_values.push((CLIENT_PID_KEY.to_string(), pid.to_string()));
+            record.key_values.push((CLIENT_NAME_KEY.to_string(), name.clone()));
+            logger::submit(record);
+        }
+        Ok(())
+    })();
+    if let Err(e) = received {
+        eprintln!("ERROR::COLLECTING FROM CLIENT {}: {}", pid, e);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::log;
+use crate::models::config::{config, LoggerConfig};
+use crate::models::error::LoggerError;
+use crate::models::level::Level;
+use crate::models::rotation;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+lazy_static::lazy_static! {
+    static ref ENFORCER: Mutex<Option<mpsc::Sender<()>>> = Mutex::new(None);
+}
+
+/// Limits on how much old log output is kept in the log directory. Only files
+/// matching the file name pattern of a configured route are ever considered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct RetentionPolicy {
+    /// Delete files last written longer ago than this.
+    pub max_age: Option<Duration>,
+    /// Keep at most this many files, deleting the oldest first.
+    pub max_files: Option<usize>,
+    /// Keep the total size of all files under this many bytes, deleting the oldest first.
+    pub max_total_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age.is_none() && self.max_files.is_none() && self.max_total_bytes.is_none()
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Deletes whatever `config.retention` says is too old or too much, oldest
+/// first, and returns the deleted paths. Files currently open for writing are
+/// never deleted.
+pub fn enforce(config: &LoggerConfig) -> Result<Vec<PathBuf>, LoggerError> {
+    let policy = config.retention;
+    if policy.is_unlimited() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&config.directory)? {
+        let entry = entry?;
+        let matches = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| config.is_log_file(name));
+        let metadata = entry.metadata()?;
+        if matches && metadata.is_file() {
+            files.push(LogFile {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+    }
+    // Newest first, so everything past the limits is the tail.
+    files.sort_by_key(|file| std::cmp::Reverse(file.modified));
+
+    let now = SystemTime::now();
+    let mut kept_files = 0;
+    let mut kept_bytes = 0u64;
+    let mut deleted = Vec::new();
+    for file in files {
+        let too_old = policy
+            .max_age
+            .is_some_and(|max_age| now.duration_since(file.modified).unwrap_or_default() > max_age);
+        let too_many = policy.max_files.is_some_and(|max_files| kept_files >= max_files);
+        let too_big = policy
+            .max_total_bytes
+            .is_some_and(|max_bytes| kept_bytes + file.size > max_bytes);
+
+        if (too_old || too_many || too_big) && rotation::remove_unless_open(&file.path)? {
+            log!(
+                source: "retention",
+                Level::Prod,
+                format!("deleted {} ({} bytes)", file.path.display(), file.size)
+            );
+            deleted.push(file.path);
+        } else {
+            kept_files += 1;
+            kept_bytes += file.size;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Asks the background enforcer to apply the retention policy soon. Requests
+/// that pile up while it is busy are folded into a single pass.
+pub fn request() {
+    if let Err(e) = send_request() {
+        eprintln!("ERROR::REQUESTING RETENTION: {}", e);
+    }
+}
+
+fn send_request() -> Result<(), LoggerError> {
+    let mut enforcer = ENFORCER.lock()?;
+    let sender = match enforcer.as_ref() {
+        Some(sender) => sender.clone(),
+        None => {
+            let (sender, receiver) = mpsc::channel::<()>();
+            thread::Builder::new()
+                .name("common_logger-retention".to_string())
+                .spawn(move || {
+                    while receiver.recv().is_ok() {
+                        while receiver.try_recv().is_ok() {}
+                        if let Err(e) = enforce(&config()) {
+                            eprintln!("ERROR::ENFORCING RETENTION: {}", e);
+                        }
+                    }
+                })?;
+            enforcer.insert(sender).clone()
+        }
+    };
+    sender.send(()).map_err(|_| LoggerError::ThreadPool)
+}
@@ -0,0 +1,551 @@
+use crate::models::binary::Interner;
+use crate::models::compression::{self, append_extension, CompressJob, Compression, Target};
+use crate::models::config::{config, LoggerConfig};
+use crate::models::error::LoggerError;
+use crate::models::durability::Durability;
+use crate::models::format;
+use crate::models::level::Level;
+use crate::models::logger::LogData;
+use crate::models::process_lock;
+use crate::models::routing::Route;
+use crate::models::retention;
+use crate::models::schedule::Schedule;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Files nobody wrote to for this long are closed; they are reopened on demand.
+const IDLE_FILE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often the scheduler checks for passed rotation boundaries.
+const SCHEDULER_TICK: Duration = Duration::from_millis(500);
+
+static SCHEDULER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Serializes everything that renames or deletes backups: rotation shifting them
+/// and the compressor swapping in finished archives.
+static BACKUPS: Mutex<()> = Mutex::new(());
+
+lazy_static::lazy_static! {
+    static ref OPEN_FILES: Mutex<HashMap<PathBuf, Arc<Mutex<RotatingFile>>>> = Mutex::new(HashMap::new());
+}
+
+pub const DEFAULT_MAX_BACKUPS: usize = 10;
+
+/// When log files are rolled over and how many old ones are kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate before a write would push the file past this many bytes.
+    pub max_bytes: Option<u64>,
+    /// How many backups (`.1` being the newest) to keep; older ones are deleted.
+    pub max_backups: usize,
+    /// Also roll over at every boundary of this schedule, in the configured time zone.
+    pub schedule: Option<Schedule>,
+    /// Compress files in the background once they are rotated or closed.
+    pub compression: Compression,
+    /// Other processes append to and rotate the same files. Every record is
+    /// then written with a single append while holding the directory lock,
+    /// and rotation first checks whether another process already did it.
+    pub multi_process: bool,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        RotationPolicy {
+            max_bytes: None,
+            max_backups: DEFAULT_MAX_BACKUPS,
+            schedule: None,
+            compression: Compression::None,
+            multi_process: false,
+        }
+    }
+}
+
+/// A log file kept open between writes, together with its current size.
+struct RotatingFile {
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+    size: u64,
+    last_write: Instant,
+    durability: Durability,
+    /// Written to since the last `fdatasync`.
+    unsynced: bool,
+    last_sync: Instant,
+    /// Start of the schedule period the current contents belong to.
+    period: Option<NaiveDateTime>,
+    /// The file name itself moves on at each boundary, so there is nothing to rename.
+    period_in_name: bool,
+    /// Sources already named in this file, for formats that intern them.
+    interner: Interner,
+}
+
+impl RotatingFile {
+    fn new(path: &Path, period_in_name: bool) -> RotatingFile {
+        RotatingFile {
+            path: path.to_path_buf(),
+            file: None,
+            size: 0,
+            last_write: Instant::now(),
+            durability: Durability::default(),
+            unsynced: false,
+            last_sync: Instant::now(),
+            period: None,
+            period_in_name,
+            interner: Interner::default(),
+        }
+    }
+
+    fn file(&mut self) -> io::Result<&mut BufWriter<File>> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => {
+                let file = open_log_file(&self.path)?;
+                self.size = file.metadata()?.len();
+                self.interner.clear();
+                BufWriter::new(file)
+            }
+        };
+        Ok(self.file.insert(file))
+    }
+
+    /// `record` laid out in the configured format for this file: with the format's header
+    /// if the file is empty, and with sources interned unless other processes
+    /// write the file too.
+    fn encode(&mut self, record: &LogData, config: &LoggerConfig) -> io::Result<Vec<u8>> {
+        self.file()?;
+        let mut bytes = if self.size == 0 { config.format.file_header() } else { Vec::new() };
+        let interner = (!config.rotation.multi_process).then_some(&mut self.interner);
+        bytes.extend(format::encode(
+            config.format,
+            config.template.as_ref(),
+            record,
+            config.timezone,
+            &config.service,
+            interner,
+        ));
+        Ok(bytes)
+    }
+
+    fn write(
+        &mut self,
+        record: &LogData,
+        durability: Durability,
+        config: &LoggerConfig,
+        now: &DateTime<FixedOffset>,
+    ) -> io::Result<()> {
+        let policy = &config.rotation;
+        self.durability = durability;
+        let _lock = process_lock::lock_parent_if(policy.multi_process, &self.path)?;
+        if policy.multi_process {
+            self.refresh()?;
+        }
+        self.file()?;
+        if let Some(schedule) = policy.schedule {
+            let period = schedule.period_start(now);
+            let stale = match self.period {
+                Some(current) => current != period,
+                // Left over from before a restart: roll it over if it belongs to an earlier period.
+                None => self.size > 0 && self.modified_period(schedule, now)? < period,
+            };
+            if stale && !self.period_in_name {
+                self.rotate(policy)?;
+            }
+            self.period = Some(period);
+        }
+        let mut bytes = self.encode(record, config)?;
+        if let Some(max_bytes) = policy.max_bytes {
+            // An empty file is never rotated, so a single oversized record still lands somewhere.
+            if self.size > 0 && self.size + bytes.len() as u64 > max_bytes {
+                self.rotate(policy)?;
+                bytes = self.encode(record, config)?;
+            }
+        }
+        let file = self.file()?;
+        file.write_all(&bytes)?;
+        if policy.multi_process {
+            // The record has to hit the file before another process gets the lock.
+            file.flush()?;
+        }
+        self.size += bytes.len() as u64;
+        self.last_write = Instant::now();
+        self.unsynced = true;
+        match durability {
+            Durability::SyncProd if record.level == Level::Prod => self.sync(),
+            Durability::SyncEvery(interval) if self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops the handle if another process has rotated the file away since we
+    /// opened it, and otherwise picks up the size the other writers left it
+    /// at. Returns whether the handle was stale. Callers hold the directory lock.
+    fn refresh(&mut self) -> io::Result<bool> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(false);
+        };
+        file.flush()?;
+        let open = file.get_ref().metadata()?;
+        if inode(&self.path) == Some(open.ino()) {
+            self.size = open.len();
+            return Ok(false);
+        }
+        self.file = None;
+        self.size = 0;
+        // The new file may already hold records of the current period.
+        self.period = None;
+        Ok(true)
+    }
+
+    /// Flushes the buffer and waits for the data to reach the disk.
+    fn sync(&mut self) -> io::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            file.flush()?;
+            if self.unsynced {
+                file.get_ref().sync_data()?;
+            }
+        }
+        self.unsynced = false;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Background housekeeping: pushes out buffers nobody else flushes and
+    /// performs interval syncs that came due while no records arrived.
+    fn tick(&mut self) -> io::Result<()> {
+        match self.durability {
+            Durability::None => self.flush(),
+            Durability::SyncEvery(interval) if self.unsynced && self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    fn modified_period(&mut self, schedule: Schedule, now: &DateTime<FixedOffset>) -> io::Result<NaiveDateTime> {
+        let modified = self.file()?.get_ref().metadata()?.modified()?;
+        let modified = DateTime::<Utc>::from(modified).with_timezone(now.offset());
+        Ok(schedule.period_start(&modified))
+    }
+
+    /// Rolls the file over if `now` is past the end of its period. Files whose
+    /// name already changes with the period are simply closed.
+    fn roll_elapsed(&mut self, policy: &RotationPolicy, now: &DateTime<FixedOffset>) -> io::Result<()> {
+        let (Some(schedule), Some(current)) = (policy.schedule, self.period) else {
+            return Ok(());
+        };
+        let period = schedule.period_start(now);
+        if current == period {
+            return Ok(());
+        }
+        let _lock = process_lock::lock_parent_if(policy.multi_process, &self.path)?;
+        if policy.multi_process && self.refresh()? {
+            // Another process has rolled it over already.
+            return Ok(());
+        }
+        if self.period_in_name {
+            if let Some(mut file) = self.file.take() {
+                file.flush()?;
+                if policy.compression != Compression::None && self.path.exists() {
+                    enqueue(CompressJob {
+                        source: File::open(&self.path)?,
+                        target: Target::Closed(self.path.clone()),
+                        compression: policy.compression,
+                    });
+                }
+            }
+            self.size = 0;
+            self.period = None;
+            retention::request();
+        } else {
+            self.rotate(policy)?;
+            self.period = Some(period);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Shifts `name.N-1` to `name.N` down to `name` to `name.1`, dropping the
+    /// oldest backup. The caller holds this file's lock, so no record can be
+    /// written between closing the old file and opening the new one.
+    fn rotate(&mut self, policy: &RotationPolicy) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        self.size = 0;
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let _backups = BACKUPS.lock().unwrap_or_else(PoisonError::into_inner);
+        if policy.max_backups == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for oldest in backup_variants(&self.path, policy.max_backups) {
+            remove_if_exists(&oldest)?;
+        }
+        for index in (1..policy.max_backups).rev() {
+            let older = backup_variants(&self.path, index + 1);
+            for (from, to) in backup_variants(&self.path, index).into_iter().zip(older) {
+                if from.exists() {
+                    fs::rename(&from, to)?;
+                }
+            }
+        }
+        let newest = backup_path(&self.path, 1);
+        fs::rename(&self.path, &newest)?;
+        retention::request();
+        if policy.compression != Compression::None {
+            enqueue(CompressJob {
+                source: File::open(&newest)?,
+                target: Target::Backup {
+                    base: self.path.clone(),
+                    max_backups: policy.max_backups,
+                },
+                compression: policy.compression,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn enqueue(job: CompressJob) {
+    // Compression is best effort; the uncompressed file is still there if it fails.
+    if let Err(e) = compression::enqueue(job) {
+        eprintln!("ERROR::QUEUEING LOG FILE FOR COMPRESSION: {}", e);
+    }
+}
+
+/// Backup `index` of `path` under every name it can have: plain and compressed.
+fn backup_variants(path: &Path, index: usize) -> Vec<PathBuf> {
+    let plain = backup_path(path, index);
+    let mut variants = vec![plain.clone()];
+    variants.extend(Compression::EXTENSIONS.iter().map(|ext| append_extension(&plain, ext)));
+    variants
+}
+
+fn inode(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.ino())
+}
+
+/// Swaps a finished archive in for the numbered backup of `base` that is the
+/// file with `inode`, wherever rotation has shifted it in the meantime. If it
+/// has been dropped as the oldest backup, the archive is left for the caller to delete.
+pub(crate) fn replace_backup(
+    base: &Path,
+    max_backups: usize,
+    inode_number: u64,
+    archive: &Path,
+    extension: &str,
+) -> Result<(), LoggerError> {
+    let _lock = process_lock::lock_parent_if(config().rotation.multi_process, base)?;
+    let _backups = BACKUPS.lock()?;
+    for index in 1..=max_backups {
+        let original = backup_path(base, index);
+        if inode(&original) == Some(inode_number) {
+            fs::rename(archive, append_extension(&original, extension))?;
+            fs::remove_file(&original)?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Swaps a finished archive in for a file closed at the end of its period,
+/// unless a late record has reopened it since.
+pub(crate) fn replace_closed(path: &Path, inode_number: u64, archive: &Path, extension: &str) -> Result<(), LoggerError> {
+    // Holding the registry keeps new handles for `path` from appearing meanwhile.
+    let files = OPEN_FILES.lock()?;
+    let handle = files.get(path).map(|file| file.lock()).transpose()?;
+    let _lock = process_lock::lock_parent_if(config().rotation.multi_process, path)?;
+    if handle.as_ref().is_some_and(|file| file.file.is_some()) || inode(path) != Some(inode_number) {
+        return Ok(());
+    }
+    fs::rename(archive, append_extension(path, extension))?;
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+/// `app.log` with index 3 becomes `app.log.3`.
+pub fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Opens `path` for appending, recreating its directory if it was removed
+/// while the logger was running.
+fn open_log_file(path: &Path) -> io::Result<File> {
+    let open = || OpenOptions::new().create(true).append(true).open(path);
+    match open() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            open()
+        }
+        result => result,
+    }
+}
+
+fn handle(path: &Path, period_in_name: bool) -> Arc<Mutex<RotatingFile>> {
+    let mut files = OPEN_FILES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(file) = files.get(path) {
+        return Arc::clone(file);
+    }
+    // Yesterday's files are not written to any more; close them as new ones appear.
+    // Only handles no worker is holding right now are candidates.
+    files.retain(|_, file| {
+        Arc::strong_count(file) > 1
+            || file
+                .lock()
+                .map(|file| file.last_write.elapsed() < IDLE_FILE_TIMEOUT)
+                .unwrap_or(false)
+    });
+    let file = Arc::new(Mutex::new(RotatingFile::new(path, period_in_name)));
+    files.insert(path.to_path_buf(), Arc::clone(&file));
+    // A new file name is a rollover of its own, e.g. the first record of a new day.
+    retention::request();
+    file
+}
+
+/// Deletes `path` unless the logger holds it open, and reports whether it did.
+pub(crate) fn remove_unless_open(path: &Path) -> Result<bool, LoggerError> {
+    // Holding the registry keeps new handles for `path` from appearing meanwhile.
+    let files = OPEN_FILES.lock()?;
+    if files.contains_key(path) {
+        return Ok(false);
+    }
+    let _lock = process_lock::lock_parent_if(config().rotation.multi_process, path)?;
+    let _backups = BACKUPS.lock()?;
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn open_handles() -> Vec<Arc<Mutex<RotatingFile>>> {
+    OPEN_FILES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// Appends `record` to `path`, a file of `route`, rotating first if the size
+/// limit or schedule in `config` says so.
+pub fn append(
+    path: &Path,
+    route: &Route,
+    record: &LogData,
+    config: &LoggerConfig,
+    now: &DateTime<FixedOffset>,
+) -> Result<(), LoggerError> {
+    let pattern = route.file_name_pattern(&config.file_name_pattern);
+    let period_in_name = config
+        .rotation
+        .schedule
+        .is_some_and(|schedule| pattern.changes_every(schedule));
+    let file = handle(path, period_in_name);
+    let mut file = file.lock()?;
+    file.write(record, route.durability.unwrap_or(config.durability), config, now)?;
+    Ok(())
+}
+
+/// Flushes files whose durability asks for it at the end of a worker's batch.
+pub fn flush_batch() -> Result<(), LoggerError> {
+    for file in open_handles() {
+        let mut file = file.lock()?;
+        if file.durability.flushes_batches() {
+            file.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Flushes every open file and, with `sync`, waits for it to reach the disk.
+pub fn flush_all(sync: bool) -> Result<(), LoggerError> {
+    for file in open_handles() {
+        let mut file = file.lock()?;
+        if sync {
+            file.sync()?;
+        } else {
+            file.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Rolls over every open file whose schedule period has ended.
+pub fn rotate_elapsed(config: &LoggerConfig) -> Result<(), LoggerError> {
+    if config.rotation.schedule.is_none() {
+        return Ok(());
+    }
+    let now = config.timezone.now();
+    for file in open_handles() {
+        file.lock()?.roll_elapsed(&config.rotation, &now)?;
+    }
+    Ok(())
+}
+
+fn tick_all() -> Result<(), LoggerError> {
+    for file in open_handles() {
+        file.lock()?.tick()?;
+    }
+    Ok(())
+}
+
+/// Starts the thread that enforces schedule boundaries and durability
+/// deadlines while no records arrive.
+pub fn start_scheduler() -> Result<(), LoggerError> {
+    if SCHEDULER_STARTED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let spawned = thread::Builder::new()
+        .name("common_logger-rotation".to_string())
+        .spawn(|| loop {
+            thread::sleep(SCHEDULER_TICK);
+            if let Err(e) = rotate_elapsed(&config()) {
+                eprintln!("ERROR::SCHEDULED ROTATION: {}", e);
+            }
+            if let Err(e) = tick_all() {
+                eprintln!("ERROR::FLUSHING LOG FILES: {}", e);
+            }
+        });
+    if let Err(e) = spawned {
+        SCHEDULER_STARTED.store(false, Ordering::SeqCst);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Rotates every file currently held open, regardless of its size. Meant for
+/// logrotate-style tooling that wants to take the current file away.
+pub fn rotate_all(policy: &RotationPolicy) -> Result<(), LoggerError> {
+    for file in open_handles() {
+        let mut file = file.lock()?;
+        let _lock = process_lock::lock_parent_if(policy.multi_process, &file.path)?;
+        if policy.multi_process {
+            file.refresh()?;
+        }
+        file.rotate(policy)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::models::durability::Durability;
+use crate::models::error::LoggerError;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::naming::FileNamePattern;
+use chrono::{DateTime, FixedOffset};
+use std::path::{Path, PathBuf};
+
+/// How a route splits records across files.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RouteKind {
+    /// Every record in one file; `{level}` renders as `All`.
+    Combined,
+    /// One file per level, the historical layout.
+    PerLevel,
+    /// One file per level holding that level and everything more severe, so
+    /// the `Debug` file has both `Prod` and `Debug` records.
+    Cumulative,
+    /// One file per record source.
+    PerSource,
+}
+
+impl RouteKind {
+    /// The file name pattern used when a route does not bring its own.
+    fn default_pattern(&self) -> &'static str {
+        match self {
+            RouteKind::Combined => "{date}.log",
+            RouteKind::PerLevel => crate::models::naming::DEFAULT_FILE_NAME_PATTERN,
+            RouteKind::Cumulative => "{date}_{level}+.log",
+            RouteKind::PerSource => "{date}_{source}.log",
+        }
+    }
+}
+
+impl std::str::FromStr for RouteKind {
+    type Err = LoggerError;
+
+    fn from_str(kind: &str) -> Result<RouteKind, Self::Err> {
+        match kind.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "combined" => Ok(RouteKind::Combined),
+            "perlevel" => Ok(RouteKind::PerLevel),
+            "cumulative" => Ok(RouteKind::Cumulative),
+            "persource" => Ok(RouteKind::PerSource),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown route `{}`", kind))),
+        }
+    }
+}
+
+/// One rule deciding which files a record is written to. A record is written
+/// once to every file of every route that accepts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub(crate) kind: RouteKind,
+    pub(crate) pattern: Option<FileNamePattern>,
+    pub(crate) max_level: LevelFilter,
+    /// `None` follows the logger-wide durability.
+    pub(crate) durability: Option<Durability>,
+}
+
+impl Route {
+    pub fn new(kind: RouteKind) -> Route {
+        let pattern = match kind {
+            RouteKind::PerLevel => None,
+            kind => kind.default_pattern().parse().ok(),
+        };
+        Route {
+            kind,
+            pattern,
+            max_level: LevelFilter::Trace,
+            durability: None,
+        }
+    }
+
+    /// Uses `pattern` for this route's file names instead of the default one.
+    pub fn pattern(mut self, pattern: &str) -> Result<Route, LoggerError> {
+        self.pattern = Some(pattern.parse()?);
+        Ok(self)
+    }
+
+    /// Only takes records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> Route {
+        self.max_level = max_level;
+        self
+    }
+
+    /// How hard this route's files work to get records onto the disk,
+    /// instead of the logger-wide durability.
+    pub fn durability(mut self, durability: Durability) -> Route {
+        self.durability = Some(durability);
+        self
+    }
+
+    #[inline]
+    pub fn kind(&self) -> RouteKind {
+        self.kind
+    }
+
+    /// The pattern this route names its files with. Per-level routes without
+    /// their own pattern follow the logger-wide `file_name_pattern`; the other
+    /// kinds start out with a default of their own.
+    pub fn file_name_pattern<'a>(&'a self, fallback: &'a FileNamePattern) -> &'a FileNamePattern {
+        self.pattern.as_ref().unwrap_or(fallback)
+    }
+
+    /// Every file under `directory` this route writes a `level`/`source` record to.
+    pub fn files_for(
+        &self,
+        fallback: &FileNamePattern,
+        directory: &Path,
+        time: &DateTime<FixedOffset>,
+        level: Level,
+        source: &str,
+    ) -> Vec<PathBuf> {
+        if level > self.max_level {
+            return Vec::new();
+        }
+        let pattern = self.file_name_pattern(fallback);
+        let names = match self.kind {
+            RouteKind::Combined => vec![pattern.render(time, None, source)],
+            RouteKind::PerLevel | RouteKind::PerSource => vec![pattern.render(time, Some(level), source)],
+            RouteKind::Cumulative => Level::iter()
+                .filter(|threshold| level <= *threshold && *threshold <= self.max_level)
+                .map(|threshold| pattern.render(time, Some(threshold), source))
+                .collect(),
+        };
+        names.into_iter().map(|name| directory.join(name)).collect()
+    }
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Route::new(RouteKind::PerLevel)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::models::error::LoggerError;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDateTime, Offset, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// Time zone used for file names, record timestamps and rotation boundaries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LogTimeZone {
+    #[default]
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl LogTimeZone {
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        self.convert(Utc::now())
+    }
+
+    pub fn convert(&self, time: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            LogTimeZone::Utc => time.with_timezone(&Utc.fix()),
+            LogTimeZone::Local => time.with_timezone(&Local).fixed_offset(),
+            LogTimeZone::Fixed(offset) => time.with_timezone(offset),
+        }
+    }
+}
+
+impl FromStr for LogTimeZone {
+    type Err = LoggerError;
+
+    /// Accepts `utc`, `local` or an offset such as `+02:00`.
+    fn from_str(zone: &str) -> Result<LogTimeZone, Self::Err> {
+        if zone.eq_ignore_ascii_case("utc") || zone == "Z" {
+            return Ok(LogTimeZone::Utc);
+        }
+        if zone.eq_ignore_ascii_case("local") {
+            return Ok(LogTimeZone::Local);
+        }
+        zone.parse::<FixedOffset>()
+            .map(LogTimeZone::Fixed)
+            .map_err(|_| LoggerError::InvalidConfig(format!("unknown time zone `{}`", zone)))
+    }
+}
+
+impl fmt::Display for LogTimeZone {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogTimeZone::Utc => fmt.write_str("utc"),
+            LogTimeZone::Local => fmt.write_str("local"),
+            LogTimeZone::Fixed(offset) => write!(fmt, "{}", offset),
+        }
+    }
+}
+
+/// How often files are rolled over regardless of their size.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Schedule {
+    /// Mostly useful for tests.
+    Minutely,
+    Hourly,
+    Daily,
+    /// Weeks start on Monday; file names need `{week}` rather than `{date}`.
+    Weekly,
+}
+
+impl Schedule {
+    /// The start of the period `time` falls into, in `time`'s own wall clock.
+    pub fn period_start(&self, time: &DateTime<FixedOffset>) -> NaiveDateTime {
+        let local = time.naive_local();
+        let midnight = local.date().and_hms_opt(0, 0, 0).unwrap_or(local);
+        match self {
+            Schedule::Minutely => midnight + Duration::minutes(i64::from(local.hour() * 60 + local.minute())),
+            Schedule::Hourly => midnight + Duration::hours(i64::from(local.hour())),
+            Schedule::Daily => midnight,
+            Schedule::Weekly => midnight - Duration::days(i64::from(local.weekday().num_days_from_monday())),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = LoggerError;
+
+    fn from_str(schedule: &str) -> Result<Schedule, Self::Err> {
+        match schedule.to_ascii_lowercase().as_str() {
+            "minutely" => Ok(Schedule::Minutely),
+            "hourly" => Ok(Schedule::Hourly),
+            "daily" => Ok(Schedule::Daily),
+            "weekly" => Ok(Schedule::Weekly),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown rotation schedule `{}`", schedule))),
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use crate::models::error::LoggerError;
+use crate::models::network::ConnectionState;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an idle sender sleeps between checks; a queued record wakes it sooner.
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+
+/// Where an output's sender thread is kept, from one record to the next.
+pub(crate) type Slot<O> = Mutex<Option<Arc<Sender<O>>>>;
+
+/// An output whose records are queued by the logging workers and sent from
+/// a thread of its own, so that logging never waits for the peer.
+pub(crate) trait Output: Clone + PartialEq + Send + Sync + 'static {
+    /// What one queued record is.
+    type Record: Send + 'static;
+    /// Names the sender thread, `common_logger-<kind>`, and its errors.
+    const KIND: &'static str;
+
+    /// Where records go, as error messages and stats show it.
+    fn destination(&self) -> String;
+    fn queue_capacity(&self) -> usize;
+    fn batching(&self) -> Batching;
+    /// What `record` counts for against [`Batching::max_bytes`].
+    fn record_len(record: &Self::Record) -> usize;
+    /// The sender thread; it ends once [`Sender::next_batch`] returns `None`.
+    fn run(sender: Arc<Sender<Self>>);
+}
+
+/// When queued records are taken off together: once `max_records` of them
+/// or `max_bytes` are queued, or the oldest has waited `max_delay`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Batching {
+    pub(crate) max_records: usize,
+    pub(crate) max_bytes: usize,
+    pub(crate) max_delay: Duration,
+}
+
+impl Batching {
+    /// Everything queued, as soon as it is.
+    pub(crate) const IMMEDIATE: Batching = Batching {
+        max_records: usize::MAX,
+        max_bytes: usize::MAX,
+        max_delay: Duration::ZERO,
+    };
+}
+
+/// Exponential backoff between failed attempts, doubling up to a limit.
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max: Duration) -> Backoff {
+        Backoff { initial, max, next: initial }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+/// Records queued with when they were, and what the sender last saw.
+pub(crate) struct Queue<R> {
+    records: VecDeque<(Instant, R)>,
+    bytes: usize,
+    /// Taken off `records` and being sent.
+    in_flight: usize,
+    flush_requested: bool,
+    pub(crate) state: ConnectionState,
+    pub(crate) last_status: Option<u16>,
+    pub(crate) last_error: Option<String>,
+}
+
+impl<R> Queue<R> {
+    /// Records waiting to be sent, or being sent.
+    pub(crate) fn queued(&self) -> usize {
+        self.records.len() + self.in_flight
+    }
+}
+
+/// What the logging workers and the sender thread share.
+pub(crate) struct Sender<O: Output> {
+    pub(crate) output: O,
+    queue: Mutex<Queue<O::Record>>,
+    ready: Condvar,
+    stopped: AtomicBool,
+    pub(crate) sent: AtomicU64,
+    /// Records dropped because the queue was full or they were given up on.
+    pub(crate) dropped: AtomicU64,
+    /// Connections established after the first one.
+    pub(crate) reconnects: AtomicU64,
+    pub(crate) sent_batches: AtomicU64,
+    pub(crate) failed_batches: AtomicU64,
+}
+
+impl<O: Output> Sender<O> {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Queue<O::Record>> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_state(&self, state: ConnectionState, error: Option<String>) {
+        let mut queue = self.lock();
+        queue.state = state;
+        if error.is_some() {
+            queue.last_error = error;
+        }
+        // `flush` waits on the same condition variable.
+        self.ready.notify_all();
+    }
+
+    /// Whether a batch should go now, or else how long until the oldest record is due.
+    fn due(&self, queue: &Queue<O::Record>) -> Result<(), Duration> {
+        let batching = self.output.batching();
+        let Some((oldest, _)) = queue.records.front() else {
+            return Err(IDLE_WAIT);
+        };
+        if queue.flush_requested
+            || queue.records.len() >= batching.max_records
+            || queue.bytes >= batching.max_bytes
+            || self.is_stopped()
+        {
+            return Ok(());
+        }
+        match batching.max_delay.checked_sub(oldest.elapsed()) {
+            Some(left) if !left.is_zero() => Err(left),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits for the next batch to fall due and takes it off the queue, within
+    /// the batching limits. Once the output is stopped, what is still queued
+    /// comes in one last round of batches, then `None`.
+    pub(crate) fn next_batch(&self) -> Option<Vec<(Instant, O::Record)>> {
+        let batching = self.output.batching();
+        let mut queue = self.lock();
+        loop {
+            match self.due(&queue) {
+                Ok(()) => break,
+                Err(_) if self.is_stopped() => return None,
+                Err(wait) => {
+                    queue = self.ready.wait_timeout(queue, wait).unwrap_or_else(PoisonError::into_inner).0;
+                }
+            }
+        }
+        let mut batch = Vec::new();
+        let mut bytes = 0;
+        while let Some((_, record)) = queue.records.front() {
+            let len = O::record_len(record);
+            let full = batch.len() >= batching.max_records || bytes + len > batching.max_bytes;
+            if full && !batch.is_empty() {
+                break;
+            }
+            bytes += len;
+            if let Some(record) = queue.records.pop_front() {
+                batch.push(record);
+            }
+        }
+        queue.bytes -= bytes;
+        if queue.records.is_empty() {
+            queue.flush_requested = false;
+        }
+        queue.in_flight = batch.len();
+        Some(batch)
+    }
+
+    /// `count` records of the batch in flight were delivered.
+    pub(crate) fn delivered(&self, count: usize) {
+        self.sent.fetch_add(count as u64, Ordering::Relaxed);
+        let mut queue = self.lock();
+        queue.in_flight = queue.in_flight.saturating_sub(count);
+        self.ready.notify_all();
+    }
+
+    /// `count` records of the batch in flight were given up on.
+    pub(crate) fn give_up(&self, count: usize) {
+        self.dropped.fetch_add(count as u64, Ordering::Relaxed);
+        let mut queue = self.lock();
+        queue.in_flight = queue.in_flight.saturating_sub(count);
+        self.ready.notify_all();
+    }
+
+    /// Puts records that could not be delivered back at the front of the
+    /// queue, in the order they were queued, and marks the peer unreachable.
+    pub(crate) fn requeue(&self, mut records: Vec<(Instant, O::Record)>, error: String) {
+        records.sort_by_key(|(at, _)| *at);
+        let mut queue = self.lock();
+        queue.in_flight = queue.in_flight.saturating_sub(records.len());
+        for record in records.into_iter().rev() {
+            queue.bytes += O::record_len(&record.1);
+            queue.records.push_front(record);
+        }
+        self.trim(&mut queue);
+        queue.state = ConnectionState::Disconnected;
+        queue.last_error = Some(error);
+        self.ready.notify_all();
+    }
+
+    /// Drops the oldest records beyond the queue capacity.
+    fn trim(&self, queue: &mut Queue<O::Record>) {
+        while queue.records.len() > self.output.queue_capacity() {
+            if let Some((_, record)) = queue.records.pop_front() {
+                queue.bytes -= O::record_len(&record);
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Waits out `backoff`, unless the output is stopped first, and doubles
+    /// it; false once the output is stopped.
+    pub(crate) fn back_off(&self, backoff: &mut Backoff) -> bool {
+        let queue = self.lock();
+        let _ = self.ready.wait_timeout_while(queue, backoff.next, |_| !self.is_stopped());
+        backoff.next = (backoff.next * 2).min(backoff.max);
+        !self.is_stopped()
+    }
+
+    /// Drops whatever is still queued.
+    pub(crate) fn discard(&self) {
+        let mut queue = self.lock();
+        self.dropped.fetch_add(queue.records.len() as u64, Ordering::Relaxed);
+        queue.records.clear();
+        queue.bytes = 0;
+        self.ready.notify_all();
+    }
+}
+
+/// The body of a sender keeping one connection: takes batches as they fall
+/// due and connects whenever there is no connection. `deliver` removes what
+/// it delivered from the batch; on failure the rest is queued again, whole,
+/// for a new connection after the backoff. Once the output is stopped, what
+/// is queued gets one last attempt and is dropped if that fails.
+pub(crate) fn run_connected<O: Output, C>(
+    sender: &Sender<O>,
+    mut backoff: Backoff,
+    connect: impl Fn(&O) -> io::Result<C>,
+    mut deliver: impl FnMut(&Sender<O>, &mut C, &mut Vec<(Instant, O::Record)>) -> io::Result<()>,
+) {
+    let mut connection: Option<C> = None;
+    let mut connected_before = false;
+    while let Some(mut batch) = sender.next_batch() {
+        let connected = match connection.as_mut() {
+            Some(connected) => connected,
+            None => {
+                sender.set_state(ConnectionState::Connecting, None);
+                match connect(&sender.output) {
+                    Ok(connected) => {
+                        if connected_before {
+                            sender.reconnects.fetch_add(1, Ordering::Relaxed);
+                        }
+                        connected_before = true;
+                        sender.set_state(ConnectionState::Connected, None);
+                        connection.insert(connected)
+                    }
+                    Err(e) => {
+                        sender.requeue(batch, e.to_string());
+                        if !sender.back_off(&mut backoff) {
+                            sender.discard();
+                            return;
+                        }
+                        continue;
+                    }
+                }
+            }
+        };
+        match deliver(sender, connected, &mut batch) {
+            Ok(()) => backoff.reset(),
+            Err(e) => {
+                connection = None;
+                sender.requeue(batch, e.to_string());
+                if !sender.back_off(&mut backoff) {
+                    sender.discard();
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The sender for `output`, replacing one started for an earlier configuration.
+fn start<O: Output>(slot: &Slot<O>, output: &O) -> Result<Arc<Sender<O>>, LoggerError> {
+    let mut slot = slot.lock()?;
+    if let Some(sender) = slot.as_ref().filter(|sender| sender.output == *output) {
+        return Ok(Arc::clone(sender));
+    }
+    stop(&mut slot);
+    let sender = Arc::new(Sender {
+        output: output.clone(),
+        queue: Mutex::new(Queue {
+            records: VecDeque::new(),
+            bytes: 0,
+            in_flight: 0,
+            flush_requested: false,
+            state: ConnectionState::Connecting,
+            last_status: None,
+            last_error: None,
+        }),
+        ready: Condvar::new(),
+        stopped: AtomicBool::new(false),
+        sent: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+        reconnects: AtomicU64::new(0),
+        sent_batches: AtomicU64::new(0),
+        failed_batches: AtomicU64::new(0),
+    });
+    let running = Arc::clone(&sender);
+    thread::Builder::new()
+        .name(format!("common_logger-{}", O::KIND))
+        .spawn(move || O::run(running))?;
+    *slot = Some(Arc::clone(&sender));
+    Ok(sender)
+}
+
+/// Tells the sender to finish up; it makes one last attempt at what it holds, then ends.
+fn stop<O: Output>(slot: &mut Option<Arc<Sender<O>>>) -> Option<Arc<Sender<O>>> {
+    let sender = slot.take()?;
+    sender.stopped.store(true, Ordering::SeqCst);
+    sender.ready.notify_all();
+    Some(sender)
+}
+
+/// Queues `record` with the sender kept in `slot`, starting one for
+/// `output` first if need be; never waits for the peer.
+pub(crate) fn enqueue<O: Output>(slot: &Slot<O>, output: &O, record: O::Record) {
+    let sender = match start(slot, output) {
+        Ok(sender) => sender,
+        Err(e) => {
+            eprintln!(
+                "ERROR::STARTING {} OUTPUT {}: {}",
+                O::KIND.to_ascii_uppercase(),
+                output.destination(),
+                e
+            );
+            return;
+        }
+    };
+    let mut queue = sender.lock();
+    queue.bytes += O::record_len(&record);
+    queue.records.push_back((Instant::now(), record));
+    sender.trim(&mut queue);
+    sender.ready.notify_all();
+}
+
+/// Sends every pending record now, waiting until `deadline` for it to go
+/// out; records for a peer that cannot be reached are not waited for.
+pub(crate) fn flush<O: Output>(slot: &Slot<O>, deadline: Instant) {
+    let Some(sender) = slot.lock().unwrap_or_else(PoisonError::into_inner).clone() else {
+        return;
+    };
+    let mut queue = sender.lock();
+    queue.flush_requested = !queue.records.is_empty();
+    sender.ready.notify_all();
+    wait_sent(&sender, queue, deadline);
+}
+
+fn wait_sent<O: Output>(sender: &Sender<O>, mut queue: MutexGuard<'_, Queue<O::Record>>, deadline: Instant) {
+    while queue.queued() > 0 && queue.state != ConnectionState::Disconnected {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            return;
+        };
+        queue = sender.ready.wait_timeout(queue, left).unwrap_or_else(PoisonError::into_inner).0;
+    }
+}
+
+/// Stops the sender in `slot`, if there is one.
+pub(crate) fn close<O: Output>(slot: &Slot<O>) {
+    stop(&mut slot.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+/// Stops the sender in `slot`, if there is one, and waits until `deadline`
+/// for its last attempt at what it still holds.
+pub(crate) fn shut_down<O: Output>(slot: &Slot<O>, deadline: Instant) {
+    if let Some(sender) = stop(&mut slot.lock().unwrap_or_else(PoisonError::into_inner)) {
+        wait_sent(&sender, sender.lock(), deadline);
+    }
+}
+
+/// `stats` of the sender in `slot`, if the output has been used.
+pub(crate) fn stats<O: Output, S>(slot: &Slot<O>, stats: impl FnOnce(&Sender<O>, &Queue<O::Record>) -> S) -> Option<S> {
+    let sender = slot.lock().unwrap_or_else(PoisonError::into_inner).clone()?;
+    let queue = sender.lock();
+    Some(stats(&sender, &queue))
+}
@@ -0,0 +1,38 @@
+use crate::models::naming::HOSTNAME;
+use crate::models::syslog::default_app_name;
+
+/// Who writes the records, for layouts that name the service and host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceInfo {
+    pub(crate) name: String,
+    pub(crate) version: Option<String>,
+    pub(crate) host: String,
+}
+
+impl ServiceInfo {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    #[inline]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+}
+
+impl Default for ServiceInfo {
+    /// The running executable's name, no version, and this machine's hostname.
+    fn default() -> Self {
+        ServiceInfo {
+            name: default_app_name(),
+            version: None,
+            host: HOSTNAME.clone(),
+        }
+    }
+}
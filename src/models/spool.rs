@@ -0,0 +1,238 @@
+use crate::models::binary;
+use crate::models::constants::LOGGING_BATCH_SIZE;
+use crate::models::error::LoggerError;
+use crate::models::logger::LogData;
+use crossbeam_queue::SegQueue;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Spool files are `.common_logger-<pid>-<start>.spool` in the log directory.
+pub const SPOOL_EXTENSION: &str = "spool";
+const SPOOL_PREFIX: &str = ".common_logger-";
+
+const MAGIC: &[u8; 8] = b"ULSPOOL2";
+/// The magic followed by the offset of the first record not replayed yet.
+const HEADER_LEN: u64 = 16;
+/// Records larger than this are taken for a torn or corrupt file.
+const MAX_RECORD_LEN: u32 = 64 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref SPOOL: Mutex<Option<Spool>> = Mutex::new(None);
+}
+
+/// When this process started logging, in nanoseconds since the epoch.
+static STARTED: Lazy<u128> = Lazy::new(|| {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos())
+});
+
+/// This process's spool file name. The pid alone is not enough: a restarted
+/// container gets the crashed run's pid, usually 1, and must still recover
+/// that run's spool rather than take it for its own.
+fn file_name() -> String {
+    format!("{}{}-{}.{}", SPOOL_PREFIX, std::process::id(), *STARTED, SPOOL_EXTENSION)
+}
+
+/// Records that did not fit in the queue, on disk in the order they were
+/// logged. The file stays `flock`ed while this process lives, which is how a
+/// later `start_logging` tells a crashed process's spool from a running one.
+struct Spool {
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+    /// Offset of the first record not handed back to the queue yet.
+    read_offset: u64,
+    /// Records written and not replayed yet.
+    backlog: usize,
+}
+
+impl Spool {
+    fn open(directory: &Path) -> io::Result<Spool> {
+        let path = directory.join(file_name());
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        file.set_len(0)?;
+        file.write_all_at(MAGIC, 0)?;
+        file.write_all_at(&HEADER_LEN.to_le_bytes(), MAGIC.len() as u64)?;
+        let writer = OpenOptions::new().append(true).open(&path)?;
+        Ok(Spool {
+            writer: BufWriter::new(writer),
+            reader: BufReader::new(file),
+            read_offset: HEADER_LEN,
+            backlog: 0,
+        })
+    }
+
+    fn append(&mut self, record: &LogData) -> io::Result<()> {
+        self.writer.write_all(&encode(record))?;
+        // What is spooled has to survive the process; that is the point of it.
+        self.writer.flush()?;
+        self.backlog += 1;
+        Ok(())
+    }
+
+    /// Reads the next `count` records back, oldest first.
+    fn take(&mut self, count: usize) -> io::Result<Vec<LogData>> {
+        self.reader.seek(SeekFrom::Start(self.read_offset))?;
+        let mut records = Vec::with_capacity(count);
+        while records.len() < count && self.backlog > 0 {
+            let Some((record, len)) = read_record(&mut self.reader)? else {
+                self.backlog = 0;
+                break;
+            };
+            self.read_offset += len;
+            self.backlog -= 1;
+            records.push(record);
+        }
+        if self.backlog == 0 {
+            // Drained: start over at the front instead of growing forever.
+            self.writer.get_ref().set_len(HEADER_LEN)?;
+            self.read_offset = HEADER_LEN;
+        }
+        self.reader
+            .get_ref()
+            .write_all_at(&self.read_offset.to_le_bytes(), MAGIC.len() as u64)?;
+        Ok(records)
+    }
+}
+
+/// `u32` length, then the record as the binary log format stores it.
+fn encode(record: &LogData) -> Vec<u8> {
+    let body = binary::encode_body(record, None);
+    let mut bytes = Vec::with_capacity(4 + body.len());
+    bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+/// The next record and the bytes it took, or `None` at the end of the file or
+/// at a record torn by a crash.
+fn read_record<R: Read>(reader: &mut R) -> io::Result<Option<(LogData, u64)>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let len = u32::from_le_bytes(len);
+    if len > MAX_RECORD_LEN {
+        return Ok(None);
+    }
+    let mut payload = vec![0u8; len as usize];
+    match reader.read_exact(&mut payload) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    Ok(binary::decode_body(&payload, &HashMap::new())
+        .ok()
+        .map(|record| (record, 4 + len as u64)))
+}
+
+fn spool<'a>(slot: &'a mut Option<Spool>, directory: &Path) -> io::Result<&'a mut Spool> {
+    let spool = match slot.take() {
+        Some(spool) => spool,
+        None => Spool::open(directory)?,
+    };
+    Ok(slot.insert(spool))
+}
+
+/// Queues `record`, unless `threshold` records are queued already or earlier
+/// ones are still spooled, in which case it goes to the spool in `directory`.
+pub(crate) fn push(record: LogData, threshold: usize, directory: &Path, queue: &Mutex<SegQueue<LogData>>) {
+    let mut slot = SPOOL.lock().unwrap_or_else(PoisonError::into_inner);
+    let queue = queue.lock().unwrap_or_else(PoisonError::into_inner);
+    let spooling = slot.as_ref().is_some_and(|spool| spool.backlog > 0);
+    if !spooling && queue.len() < threshold {
+        queue.push(record);
+        return;
+    }
+    if let Err(e) = spool(&mut slot, directory).and_then(|spool| spool.append(&record)) {
+        // Memory is the last resort; the record is never dropped.
+        eprintln!("ERROR::SPOOLING LOG RECORD: {}", e);
+        queue.push(record);
+    }
+}
+
+/// Moves spooled records back into the queue while it holds fewer than
+/// `threshold`, at most a batch at a time.
+pub(crate) fn replay(threshold: usize, queue: &Mutex<SegQueue<LogData>>) -> Result<(), LoggerError> {
+    let mut slot = SPOOL.lock()?;
+    let Some(spool) = slot.as_mut().filter(|spool| spool.backlog > 0) else {
+        return Ok(());
+    };
+    let queue = queue.lock()?;
+    let room = threshold.saturating_sub(queue.len()).min(LOGGING_BATCH_SIZE);
+    if room == 0 {
+        return Ok(());
+    }
+    for record in spool.take(room)? {
+        queue.push(record);
+    }
+    Ok(())
+}
+
+/// Takes over the spools that crashed processes left in `directory`,
+/// appending their records to this process's spool, and returns how many
+/// records were recovered.
+pub(crate) fn recover(directory: &Path) -> Result<usize, LoggerError> {
+    let own = file_name();
+    let mut recovered = 0;
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let is_spool = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(SPOOL_PREFIX) && name.ends_with(SPOOL_EXTENSION) && name != own);
+        if is_spool {
+            recovered += recover_file(&path, directory)?;
+        }
+    }
+    Ok(recovered)
+}
+
+fn recover_file(path: &Path, directory: &Path) -> Result<usize, LoggerError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        // Its process is still running.
+        return Ok(0);
+    }
+    let mut header = [0u8; HEADER_LEN as usize];
+    let mut reader = BufReader::new(file);
+    let read_offset = match reader.read_exact(&mut header) {
+        Ok(()) if &header[..MAGIC.len()] == MAGIC => {
+            u64::from_le_bytes(header[MAGIC.len()..].try_into().unwrap_or_default())
+        }
+        _ => {
+            fs::remove_file(path)?;
+            return Ok(0);
+        }
+    };
+    reader.seek(SeekFrom::Start(read_offset))?;
+
+    let mut slot = SPOOL.lock()?;
+    let spool = spool(&mut slot, directory)?;
+    let mut recovered = 0;
+    while let Some((record, _)) = read_record(&mut reader)? {
+        spool.append(&record)?;
+        recovered += 1;
+    }
+    fs::remove_file(path)?;
+    Ok(recovered)
+}
@@ -0,0 +1,47 @@
+use crate::models::fluentd::{self, FluentdStats};
+use crate::models::http::{self, HttpStats};
+use crate::models::journald;
+use crate::models::logger;
+use crate::models::loki;
+use crate::models::network::{self, NetworkStats};
+use crate::models::remote;
+use serde::Serialize;
+
+/// A snapshot of what the logger is doing, for health checks and dashboards.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LoggerStats {
+    /// Records logged but not yet handed to every output.
+    pub pending_records: usize,
+    /// The network output, once it has been used.
+    pub network: Option<NetworkStats>,
+    /// The HTTP output, once it has been used.
+    pub http: Option<HttpStats>,
+    /// The Fluentd output, once it has been used.
+    pub fluentd: Option<FluentdStats>,
+    /// The Loki output, once it has been used.
+    pub loki: Option<HttpStats>,
+    /// The remote output to a collector daemon, once it has been used.
+    pub remote: Option<NetworkStats>,
+    /// Records the journald output dropped because journald failed or was too busy to take them.
+    pub journald_dropped: u64,
+}
+
+pub fn stats() -> LoggerStats {
+    LoggerStats {
+        pending_records: logger::pending_records(),
+        network: network::stats(),
+        http: http::stats(),
+        fluentd: fluentd::stats(),
+        loki: loki::stats(),
+        remote: remote::stats(),
+        journald_dropped: journald::dropped(),
+    }
+}
+
+impl LoggerStats {
+    /// The snapshot as a JSON object, as `logger_stats` returns it over FFI.
+    pub fn to_json(&self) -> String {
+        // Strings and numbers always serialize; there is no error to report.
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
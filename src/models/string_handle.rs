@@ -6,7 +6,7 @@ pub(crate) enum StaticStr<'x> {
 
 impl<'x> StaticStr<'x> {
     #[inline]
-    fn get(&self) -> &'x str {
+    pub(crate) fn get(&self) -> &'x str {
         match *self {
             StaticStr::Static(s) => s,
             StaticStr::Borrowed(s) => s,
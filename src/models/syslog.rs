@@ -0,0 +1,509 @@
+use crate::models::connection::{self, Connection, Transport};
+use crate::models::error::LoggerError;
+use crate::models::level::{Level, LevelFilter};
+use crate::models::logger::LogData;
+use crate::models::naming::HOSTNAME;
+use crate::models::schedule::LogTimeZone;
+use chrono::SecondsFormat;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+
+/// Where local syslog daemons listen.
+pub const DEFAULT_SOCKET: &str = "/dev/log";
+
+/// Private enterprise number reserved for documentation (RFC 5612); the
+/// structured-data ID key-value pairs are sent under.
+const SD_ID: &str = "kv@32473";
+
+lazy_static::lazy_static! {
+    static ref CONNECTION: Mutex<Connection<SyslogTransport>> = Mutex::new(Connection::default());
+}
+
+/// Syslog facilities, numbered as RFC 5424 numbers them.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Facility {
+    Kern = 0,
+    #[default]
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    AuthPriv,
+    Ftp,
+    Ntp,
+    Security,
+    Console,
+    SolarisCron,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+const FACILITIES: [(&str, Facility); 24] = [
+    ("kern", Facility::Kern),
+    ("user", Facility::User),
+    ("mail", Facility::Mail),
+    ("daemon", Facility::Daemon),
+    ("auth", Facility::Auth),
+    ("syslog", Facility::Syslog),
+    ("lpr", Facility::Lpr),
+    ("news", Facility::News),
+    ("uucp", Facility::Uucp),
+    ("cron", Facility::Cron),
+    ("authpriv", Facility::AuthPriv),
+    ("ftp", Facility::Ftp),
+    ("ntp", Facility::Ntp),
+    ("security", Facility::Security),
+    ("console", Facility::Console),
+    ("solaris-cron", Facility::SolarisCron),
+    ("local0", Facility::Local0),
+    ("local1", Facility::Local1),
+    ("local2", Facility::Local2),
+    ("local3", Facility::Local3),
+    ("local4", Facility::Local4),
+    ("local5", Facility::Local5),
+    ("local6", Facility::Local6),
+    ("local7", Facility::Local7),
+];
+
+impl FromStr for Facility {
+    type Err = LoggerError;
+
+    fn from_str(facility: &str) -> Result<Facility, Self::Err> {
+        FACILITIES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(facility))
+            .map(|&(_, facility)| facility)
+            .ok_or_else(|| LoggerError::InvalidConfig(format!("unknown syslog facility `{}`", facility)))
+    }
+}
+
+/// The message layout.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum SyslogProtocol {
+    /// `<PRI>1 TIMESTAMP HOST APP PID MSGID [SD] MSG`, with the source as
+    /// MSGID and key-value pairs as structured data.
+    #[default]
+    Rfc5424,
+    /// The older BSD layout, `<PRI>Mmm dd hh:mm:ss HOST APP[PID]: MSG`, for
+    /// daemons that predate RFC 5424. Times are in the logger's time zone.
+    Rfc3164,
+}
+
+impl FromStr for SyslogProtocol {
+    type Err = LoggerError;
+
+    fn from_str(protocol: &str) -> Result<SyslogProtocol, Self::Err> {
+        match protocol.to_ascii_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "rfc5424" | "5424" => Ok(SyslogProtocol::Rfc5424),
+            "rfc3164" | "3164" | "bsd" => Ok(SyslogProtocol::Rfc3164),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown syslog protocol `{}`", protocol))),
+        }
+    }
+}
+
+/// How messages reach the syslog daemon.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SyslogTransport {
+    /// One datagram per message to a local Unix socket such as [`DEFAULT_SOCKET`].
+    Unix(PathBuf),
+    /// One datagram per message to `host:port`.
+    Udp(String),
+    /// A stream to `host:port`, messages framed by octet counting (RFC 6587).
+    Tcp(String),
+}
+
+/// The [`SyslogTransport`] variants as seen from C; the address travels separately.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyslogTransportKind {
+    Unix,
+    Udp,
+    Tcp,
+}
+
+impl SyslogTransport {
+    /// `address` is a socket path for `Unix`, where empty means [`DEFAULT_SOCKET`],
+    /// and `host:port` otherwise.
+    pub fn new(kind: SyslogTransportKind, address: &str) -> Result<SyslogTransport, LoggerError> {
+        if address.is_empty() && kind != SyslogTransportKind::Unix {
+            return Err(LoggerError::InvalidConfig("syslog address is empty".to_string()));
+        }
+        Ok(match kind {
+            SyslogTransportKind::Unix if address.is_empty() => SyslogTransport::Unix(PathBuf::from(DEFAULT_SOCKET)),
+            SyslogTransportKind::Unix => SyslogTransport::Unix(PathBuf::from(address)),
+            SyslogTransportKind::Udp => SyslogTransport::Udp(address.to_string()),
+            SyslogTransportKind::Tcp => SyslogTransport::Tcp(address.to_string()),
+        })
+    }
+}
+
+impl FromStr for SyslogTransportKind {
+    type Err = LoggerError;
+
+    fn from_str(kind: &str) -> Result<SyslogTransportKind, Self::Err> {
+        match kind.to_ascii_lowercase().as_str() {
+            "unix" => Ok(SyslogTransportKind::Unix),
+            "udp" => Ok(SyslogTransportKind::Udp),
+            "tcp" => Ok(SyslogTransportKind::Tcp),
+            _ => Err(LoggerError::InvalidConfig(format!("unknown syslog transport `{}`", kind))),
+        }
+    }
+}
+
+/// Sends records to a syslog daemon as well as to the files.
+///
+/// Levels map to severities as `Prod` → informational (6), `Debug` and
+/// `Trace` → debug (7).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyslogOutput {
+    pub(crate) transport: SyslogTransport,
+    pub(crate) facility: Facility,
+    pub(crate) app_name: String,
+    pub(crate) protocol: SyslogProtocol,
+    pub(crate) max_level: LevelFilter,
+}
+
+impl SyslogOutput {
+    /// RFC 5424 messages from facility `user`, named after the running executable.
+    pub fn new(transport: SyslogTransport) -> SyslogOutput {
+        SyslogOutput {
+            transport,
+            facility: Facility::default(),
+            app_name: default_app_name(),
+            protocol: SyslogProtocol::default(),
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    pub fn facility(mut self, facility: Facility) -> SyslogOutput {
+        self.facility = facility;
+        self
+    }
+
+    /// The APP-NAME, or tag, messages are sent under.
+    pub fn app_name(mut self, app_name: &str) -> SyslogOutput {
+        self.app_name = app_name.to_string();
+        self
+    }
+
+    pub fn protocol(mut self, protocol: SyslogProtocol) -> SyslogOutput {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Only records at `max_level` or more severe.
+    pub fn max_level(mut self, max_level: LevelFilter) -> SyslogOutput {
+        self.max_level = max_level;
+        self
+    }
+
+    #[inline]
+    pub fn transport(&self) -> &SyslogTransport {
+        &self.transport
+    }
+
+    #[inline]
+    pub fn accepts(&self, level: Level) -> bool {
+        level <= self.max_level
+    }
+}
+
+pub(crate) fn default_app_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "common_logger".to_string())
+}
+
+pub(crate) fn severity(level: Level) -> u8 {
+    match level {
+        Level::Prod => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Header fields are printable ASCII without spaces, `-` when empty.
+fn push_header_field(message: &mut String, value: &str, max_len: usize) {
+    let start = message.len();
+    message.extend(
+        value
+            .chars()
+            .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+            .take(max_len),
+    );
+    if message.len() == start {
+        message.push('-');
+    }
+}
+
+/// SD-PARAM names cannot hold `=`, spaces, `]` or `"`, and are at most 32 characters.
+fn push_param_name(message: &mut String, name: &str) {
+    let start = message.len();
+    message.extend(
+        name.chars()
+            .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+            .take(32),
+    );
+    if message.len() == start {
+        message.push('_');
+    }
+}
+
+fn push_param_value(message: &mut String, value: &str) {
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            message.push('\\');
+        }
+        message.push(c);
+    }
+}
+
+/// `record` as one syslog message, without any transport framing.
+pub fn message(output: &SyslogOutput, record: &LogData, timezone: LogTimeZone) -> String {
+    let mut message = String::with_capacity(128 + record.message.len());
+    let pri = output.facility as u8 * 8 + severity(record.level);
+    match output.protocol {
+        SyslogProtocol::Rfc5424 => {
+            let _ = write!(message, "<{}>1 ", pri);
+            message.push_str(&record.time.to_rfc3339_opts(SecondsFormat::Micros, true));
+            message.push(' ');
+            push_header_field(&mut message, &HOSTNAME, 255);
+            message.push(' ');
+            push_header_field(&mut message, &output.app_name, 48);
+            let _ = write!(message, " {} ", std::process::id());
+            push_header_field(&mut message, &record.source, 32);
+            message.push(' ');
+            if record.key_values.is_empty() {
+                message.push('-');
+            } else {
+                message.push('[');
+                message.push_str(SD_ID);
+                for (key, value) in &record.key_values {
+                    message.push(' ');
+                    push_param_name(&mut message, key);
+                    message.push_str("=\"");
+                    push_param_value(&mut message, value);
+                    message.push('"');
+                }
+                message.push(']');
+            }
+            message.push(' ');
+            message.push_str(&record.message);
+        }
+        SyslogProtocol::Rfc3164 => {
+            let time = timezone.convert(record.time).format("%b %e %H:%M:%S");
+            let _ = write!(message, "<{}>{} ", pri, time);
+            push_header_field(&mut message, &HOSTNAME, 255);
+            message.push(' ');
+            push_header_field(&mut message, &output.app_name, 32);
+            let _ = write!(message, "[{}]: ", std::process::id());
+            if !record.source.is_empty() {
+                let _ = write!(message, "[{}] ", record.source);
+            }
+            message.push_str(&record.message);
+            for (key, value) in &record.key_values {
+                let _ = write!(message, " {}={}", key, value);
+            }
+        }
+    }
+    message
+}
+
+pub(crate) enum Socket {
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Socket {
+    fn connect(transport: &SyslogTransport) -> io::Result<Socket> {
+        match transport {
+            SyslogTransport::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.set_write_timeout(Some(connection::WRITE_TIMEOUT))?;
+                socket.connect(path)?;
+                Ok(Socket::Unix(socket))
+            }
+            SyslogTransport::Udp(address) => Ok(Socket::Udp(connection::udp(address)?)),
+            SyslogTransport::Tcp(address) => Ok(Socket::Tcp(connection::tcp(address)?)),
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Socket::Unix(socket) => socket.send(message).map(drop),
+            Socket::Udp(socket) => socket.send(message).map(drop),
+            Socket::Tcp(stream) => {
+                let mut frame = format!("{} ", message.len()).into_bytes();
+                frame.extend_from_slice(message);
+                stream.write_all(&frame)
+            }
+        }
+    }
+}
+
+impl Transport for SyslogTransport {
+    type Socket = Socket;
+
+    fn connect(&self) -> io::Result<Socket> {
+        Socket::connect(self)
+    }
+}
+
+/// Sends `record` to the daemon `output` describes, connecting first if needed.
+/// While the daemon cannot be reached, records are dropped rather than queued.
+pub(crate) fn send(output: &SyslogOutput, record: &LogData, timezone: LogTimeZone) {
+    if !output.accepts(record.level) {
+        return;
+    }
+    let message = message(output, record, timezone);
+    let mut connection = CONNECTION.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(e) = connection.send(&output.transport, |socket| socket.send(message.as_bytes())) {
+        eprintln!("ERROR::SENDING TO SYSLOG {:?}: {}", output.transport, e);
+    }
+}
+
+/// Closes the connection, e.g. after the syslog output was switched off.
+pub(crate) fn close() {
+    *CONNECTION.lock().unwrap_or_else(PoisonError::into_inner) = Connection::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn output(transport: SyslogTransport) -> SyslogOutput {
+        SyslogOutput::new(transport).app_name("test-app")
+    }
+
+    #[test]
+    fn pri_is_facility_times_eight_plus_severity() {
+        let output = output(SyslogTransport::Udp("127.0.0.1:514".to_string()));
+        for (facility, level, pri) in [
+            (Facility::Kern, Level::Prod, "<6>"),
+            (Facility::User, Level::Debug, "<15>"),
+            (Facility::Local3, Level::Prod, "<158>"),
+            (Facility::Local7, Level::Trace, "<191>"),
+        ] {
+            let output = output.clone().facility(facility);
+            let message = message(&output, &LogData::test(level, "app::db", "hi"), LogTimeZone::Utc);
+            assert!(message.starts_with(pri), "{} for {:?}", message, facility);
+        }
+    }
+
+    #[test]
+    fn rfc5424_over_unix_datagram() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("log.sock");
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        let output = output(SyslogTransport::Unix(path)).facility(Facility::Local3);
+
+        send(&output, &LogData::test(Level::Prod, "app::db", "connected"), LogTimeZone::Utc);
+        let mut buf = [0u8; 1024];
+        let len = daemon.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+
+        let fields: Vec<&str> = message.splitn(8, ' ').collect();
+        assert_eq!(fields[0], "<158>1");
+        assert_eq!(fields[1], "2024-03-05T07:08:09.000000Z");
+        assert_eq!(fields[2], HOSTNAME.as_str());
+        assert_eq!(fields[3], "test-app");
+        assert_eq!(fields[4], std::process::id().to_string());
+        assert_eq!(fields[5], "app::db");
+        assert_eq!(fields[6], "-");
+        assert_eq!(fields[7], "connected");
+    }
+
+    #[test]
+    fn rfc5424_structured_data_is_escaped() {
+        let output = output(SyslogTransport::Udp("127.0.0.1:514".to_string()));
+        let record = LogData::test(Level::Prod, "app::db", "msg")
+            .key_values(&[("user", r#"a"b\c]d"#), ("bad name=x", "v")]);
+        let message = message(&output, &record, LogTimeZone::Utc);
+        assert!(
+            message.ends_with(r#" [kv@32473 user="a\"b\\c\]d" badnamex="v"] msg"#),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn rfc3164_over_udp() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = daemon.local_addr().unwrap().to_string();
+        let output = output(SyslogTransport::Udp(address))
+            .facility(Facility::Daemon)
+            .protocol(SyslogProtocol::Rfc3164);
+
+        let record = LogData::test(Level::Debug, "app::db", "slow query").key_values(&[("ms", "250")]);
+        send(&output, &record, LogTimeZone::Utc);
+        let mut buf = [0u8; 1024];
+        let len = daemon.recv(&mut buf).unwrap();
+        let expected = format!(
+            "<31>Mar  5 07:08:09 {} test-app[{}]: [app::db] slow query ms=250",
+            HOSTNAME.as_str(),
+            std::process::id()
+        );
+        assert_eq!(std::str::from_utf8(&buf[..len]).unwrap(), expected);
+    }
+
+    #[test]
+    fn tcp_frames_by_octet_counting() {
+        let daemon = TcpListener::bind("127.0.0.1:0").unwrap();
+        let output = output(SyslogTransport::Tcp(daemon.local_addr().unwrap().to_string()));
+
+        send(&output, &LogData::test(Level::Prod, "app::db", "first"), LogTimeZone::Utc);
+        send(&output, &LogData::test(Level::Prod, "app::db", "second, longer"), LogTimeZone::Utc);
+        let (mut stream, _) = daemon.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        for text in ["first", "second, longer"] {
+            let mut len = Vec::new();
+            let mut byte = [0u8; 1];
+            while stream.read_exact(&mut byte).is_ok() && byte[0] != b' ' {
+                len.push(byte[0]);
+            }
+            let len: usize = std::str::from_utf8(&len).unwrap().parse().unwrap();
+            let mut message = vec![0u8; len];
+            stream.read_exact(&mut message).unwrap();
+            let message = String::from_utf8(message).unwrap();
+            assert!(message.starts_with("<14>1 "), "{}", message);
+            assert!(message.ends_with(text), "{}", message);
+        }
+    }
+
+    #[test]
+    fn tcp_daemon_that_stops_reading_does_not_block() {
+        let daemon = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = SyslogTransport::Tcp(daemon.local_addr().unwrap().to_string());
+        let mut socket = Socket::connect(&transport).unwrap();
+        let large = vec![b'x'; 1024 * 1024];
+
+        // Nothing reads, so once the socket buffers are full a send has to give up.
+        let failed = (0..256).find_map(|_| socket.send(&large).err());
+        let kind = failed.expect("socket buffers never filled up").kind();
+        assert!(matches!(kind, io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut), "{:?}", kind);
+        drop(daemon);
+    }
+}
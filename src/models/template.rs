@@ -0,0 +1,248 @@
+use crate::models::constants::TIME_FORMAT;
+use crate::models::error::LoggerError;
+use crate::models::logger::LogData;
+use crate::models::schedule::LogTimeZone;
+use chrono::format::{Item, StrftimeItems};
+use std::fmt;
+use std::str::FromStr;
+
+/// A line layout such as `{time:%H:%M:%S%.3f} {level:>5} [{source:<20.20}] {msg} {kv}`.
+///
+/// Fields are written `{name}` or `{name:spec}`. For `time` the spec is a
+/// strftime format, in the logger's time zone. For every other field it is
+/// `[<|>|^][width][.max]`: alignment, minimum width in characters, and the
+/// number of characters after which the value is cut off. `{{` and `}}`
+/// stand for literal braces.
+///
+/// Fields: `time`, `level`, `source`, `msg`, `thread`, `thread_name`,
+/// `module`, `file`, `line`, `seq` and `kv`, the key-value pairs as `k=v`
+/// separated by spaces. Fields a record does not have render empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Time(Vec<Item<'static>>),
+    Field(Field, Spec),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Level,
+    Source,
+    Message,
+    Thread,
+    ThreadName,
+    Module,
+    File,
+    Line,
+    Sequence,
+    KeyValues,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Spec {
+    align: Align,
+    width: usize,
+    max: Option<usize>,
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Spec {
+            align: Align::Left,
+            width: 0,
+            max: None,
+        }
+    }
+}
+
+fn invalid(template: &str, reason: &str) -> LoggerError {
+    LoggerError::InvalidConfig(format!("invalid template `{}`: {}", template, reason))
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "level" => Field::Level,
+            "source" => Field::Source,
+            "msg" | "message" => Field::Message,
+            "thread" => Field::Thread,
+            "thread_name" => Field::ThreadName,
+            "module" => Field::Module,
+            "file" => Field::File,
+            "line" => Field::Line,
+            "seq" => Field::Sequence,
+            "kv" => Field::KeyValues,
+            _ => return None,
+        })
+    }
+
+    fn value(&self, record: &LogData) -> String {
+        match self {
+            Field::Level => record.level.as_str().to_string(),
+            Field::Source => record.source.clone(),
+            Field::Message => record.message.clone(),
+            Field::Thread => record.thread.to_string(),
+            Field::ThreadName => record.thread_name.clone().unwrap_or_default(),
+            Field::Module => record.module_path.clone().unwrap_or_default(),
+            Field::File => record.file.clone().unwrap_or_default(),
+            Field::Line => record.line.map(|line| line.to_string()).unwrap_or_default(),
+            Field::Sequence => record.sequence.to_string(),
+            Field::KeyValues => record
+                .key_values
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+impl Spec {
+    fn parse(spec: &str, template: &str) -> Result<Spec, LoggerError> {
+        let mut parsed = Spec::default();
+        let mut rest = spec;
+        if let Some(align) = rest.chars().next().and_then(|c| match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        }) {
+            parsed.align = align;
+            rest = &rest[1..];
+        }
+        let (width, max) = match rest.split_once('.') {
+            Some((width, max)) => (width, Some(max)),
+            None => (rest, None),
+        };
+        let number = |digits: &str| {
+            digits
+                .parse::<usize>()
+                .map_err(|_| invalid(template, &format!("bad format spec `{}`", spec)))
+        };
+        if !width.is_empty() {
+            parsed.width = number(width)?;
+        }
+        if let Some(max) = max {
+            parsed.max = Some(number(max)?);
+        }
+        Ok(parsed)
+    }
+
+    fn apply(&self, value: String, out: &mut String) {
+        let value = match self.max {
+            Some(max) if value.chars().count() > max => value.chars().take(max).collect(),
+            _ => value,
+        };
+        let pad = self.width.saturating_sub(value.chars().count());
+        let (before, after) = match self.align {
+            Align::Left => (0, pad),
+            Align::Right => (pad, 0),
+            Align::Center => (pad / 2, pad - pad / 2),
+        };
+        out.extend(std::iter::repeat_n(' ', before));
+        out.push_str(&value);
+        out.extend(std::iter::repeat_n(' ', after));
+    }
+}
+
+impl Template {
+    /// The text the template was compiled from.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Renders `record`, with times in `timezone`.
+    pub fn render(&self, record: &LogData, timezone: LogTimeZone) -> String {
+        let mut out = String::with_capacity(self.source.len() + record.message.len() + 32);
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Time(items) => {
+                    let time = timezone.convert(record.time);
+                    out.push_str(&time.format_with_items(items.iter()).to_string());
+                }
+                Part::Field(field, spec) => spec.apply(field.value(record), &mut out),
+            }
+        }
+        out
+    }
+}
+
+impl FromStr for Template {
+    type Err = LoggerError;
+
+    /// Compiles `template`, so that rendering never has to parse it again.
+    fn from_str(template: &str) -> Result<Template, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(invalid(template, "unmatched `}`")),
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| invalid(template, "unclosed `{`"))?;
+                    let placeholder = &rest[..end];
+                    chars = rest[end + 1..].chars();
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    let (name, spec) = match placeholder.split_once(':') {
+                        Some((name, spec)) => (name, Some(spec)),
+                        None => (placeholder, None),
+                    };
+                    if name == "time" {
+                        let format = spec.unwrap_or(TIME_FORMAT);
+                        let items = StrftimeItems::new(format)
+                            .parse_to_owned()
+                            .map_err(|_| invalid(template, &format!("bad time format `{}`", format)))?;
+                        parts.push(Part::Time(items));
+                        continue;
+                    }
+                    let field = Field::from_name(name)
+                        .ok_or_else(|| invalid(template, &format!("unknown field `{}`", name)))?;
+                    let spec = match spec {
+                        Some(spec) => Spec::parse(spec, template)?,
+                        None => Spec::default(),
+                    };
+                    parts.push(Part::Field(field, spec));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template {
+            source: template.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.source)
+    }
+}
@@ -1,5 +1,7 @@
 use std::sync::{mpsc, Arc, Mutex};
 
+use crate::models::error::LoggerError;
+
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
 use std::thread;
@@ -10,21 +12,27 @@ pub struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let thread = thread::spawn(move || {
-            while let Ok(job) = receiver
-                .lock()
-                .expect("ERROR::WORKER FAILED TO ACQUIRE LOCK ON RECEIVER")
-                .recv()
-            {
-                job();
-            }
-        });
-
-        Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Result<Worker, LoggerError> {
+        let thread = thread::Builder::new()
+            .name(format!("common_logger-{}", id))
+            .spawn(move || loop {
+                let job = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => {
+                        eprintln!("ERROR::WORKER FAILED TO ACQUIRE LOCK ON RECEIVER");
+                        return;
+                    }
+                };
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            })?;
+
+        Ok(Worker {
             id,
             thread: Some(thread),
-        }
+        })
     }
 }
 
@@ -34,59 +42,61 @@ pub struct ThreadPool {
 }
 
 impl ThreadPool {
-    pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
+    pub fn new(size: usize) -> Result<ThreadPool, LoggerError> {
+        if size == 0 {
+            return Err(LoggerError::ThreadPool);
+        }
 
         let (sender, receiver) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver))?);
         }
 
-        ThreadPool { workers, sender }
+        Ok(ThreadPool { workers, sender })
     }
 
-    pub fn execute<F>(&self, f: F)
+    pub fn execute<F>(&self, f: F) -> Result<(), LoggerError>
     where
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
-        self.sender.send(job).unwrap();
+        self.sender.send(job).map_err(|_| LoggerError::ThreadPool)
     }
 
-    pub fn set_size(&mut self, size: usize) {
-        assert!(size > 0);
+    pub fn set_size(&mut self, size: usize) -> Result<(), LoggerError> {
+        if size == 0 {
+            return Err(LoggerError::ThreadPool);
+        }
 
         let (new_sender, new_receiver) = mpsc::channel();
         let new_receiver = Arc::new(Mutex::new(new_receiver));
 
-        for worker in &mut self.workers {
-            self.sender.send(Box::new(|| {})).unwrap();
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
-        }
+        self.shutdown()?;
 
         self.sender = new_sender;
         self.workers.clear();
 
         for id in 0..size {
-            self.workers.push(Worker::new(id, Arc::clone(&new_receiver)));
+            self.workers.push(Worker::new(id, Arc::clone(&new_receiver))?);
         }
+        Ok(())
     }
 
-    pub fn shutdown(&mut self) {
-        for _ in &self.workers {
-            self.sender.send(Box::new(|| {})).unwrap();
-        }
+    pub fn shutdown(&mut self) -> Result<(), LoggerError> {
+        // Replacing the sender drops the old one, which makes every idle worker's
+        // `recv` fail and the worker return.
+        let (closed, _) = mpsc::channel();
+        self.sender = closed;
 
         for worker in &mut self.workers {
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                thread.join().map_err(LoggerError::from_panic)?;
             }
         }
+        Ok(())
     }
 }
 
@@ -94,15 +104,19 @@ impl Drop for ThreadPool {
     fn drop(&mut self) {
         for worker in &mut self.workers {
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                if thread.join().is_err() {
+                    eprintln!("ERROR::WORKER {} PANICKED", worker.id);
+                }
             }
         }
     }
 }
 
-pub fn thread_id() -> u64 {
-    let mut string = format!("{:?}", thread::current().id());
-    string.replace_range(0..9, "");
-    string.pop();
-    string.parse().unwrap()
-}
\ No newline at end of file
+pub fn thread_id() -> Result<u64, LoggerError> {
+    let string = format!("{:?}", thread::current().id());
+    string
+        .strip_prefix("ThreadId(")
+        .and_then(|s| s.strip_suffix(')'))
+        .and_then(|s| s.parse().ok())
+        .ok_or(LoggerError::ThreadId)
+}
@@ -10,7 +10,7 @@ use crate::models::constants::{
 use crate::models::log::{Log, LOGGER};
 use crate::models::{constants, level::LevelFilter};
 use core::fmt;
-use std::{mem, sync::atomic::Ordering};
+use std::sync::atomic::Ordering;
 
 pub(crate) fn is_ok<T, E>(t: Option<T>, e: E) -> Result<T, E> {
     match t {
@@ -32,7 +32,7 @@ pub unsafe fn set_max_level_racy(level: LevelFilter) {
 
 #[inline(always)]
 pub fn max_level() -> LevelFilter {
-    unsafe { mem::transmute(MAX_LOG_LEVEL_FILTER.load(Ordering::Relaxed)) }
+    LevelFilter::from_usize(MAX_LOG_LEVEL_FILTER.load(Ordering::Relaxed)).unwrap_or(LevelFilter::Off)
 }
 
 #[cfg(all(feature = "std", target_has_atomic = "ptr"))]
@@ -85,10 +85,8 @@ pub unsafe fn set_logger_racy(logger: &'static dyn Log) -> Result<(), SetLoggerE
             STATE.store(DO_INIT_STATE, Ordering::SeqCst);
             Ok(())
         }
-        DO_INIT_STATE => {
-            // This is just plain UB, since we were racing another initialization function
-            unreachable!("set_logger_racy must not be used with other initialization functions")
-        }
+        // We raced another initialization function; report it instead of aborting the host.
+        DO_INIT_STATE => Err(SetLoggerError(())),
         _ => Err(SetLoggerError(())),
     }
 }